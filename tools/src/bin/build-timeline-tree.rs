@@ -6,22 +6,29 @@ extern crate git2;
 #[macro_use]
 extern crate log;
 extern crate num_cpus;
+extern crate serde_json;
 extern crate tools;
 
 use std::borrow::{Borrow, Cow};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::env;
 use std::fmt;
+use std::fs::File;
 use std::io::{BufRead, BufReader, BufWriter, Read, Write};
 use std::path::{Path, PathBuf};
 use std::process::{Child, Command, Stdio};
 use std::str::from_utf8;
 use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
 use std::thread;
+use std::time::{Duration, Instant};
 
-use git2::{Blob, DiffFindOptions, ObjectType, Oid, Patch, Repository, Sort};
+use git2::{Blob, DiffAlgorithm, DiffFindOptions, DiffOptions, ObjectType, Oid, Patch, Repository, Sort};
+use serde::{Deserialize, Serialize};
 use tools::blame::LineData;
 use tools::file_format::config::{index_blame, index_timeline_history_by_source_rev, syntax_commit_to_meta, HistorySyntaxCommitMeta};
+use tools::file_format::history::timeline_common::TokenDeltaDetails;
+use tools::file_format::history::timeline_tokens::AuthorTokenRollupRecord;
 use tools::tree_sitter_support::cst_tokenizer::namespace_for_file;
 
 fn get_hg_rev(helper: &mut Child, git_oid: &Oid) -> Option<String> {
@@ -48,11 +55,113 @@ fn start_cinnabar_helper(git_repo: &Repository) -> Child {
         .unwrap()
 }
 
+/// The reverse of `start_cinnabar_helper`: resolves hg revs to the git oid
+/// they correspond to, for `.git-blame-ignore-revs`-style files that name
+/// their entries as hg revs (as `get_hg_rev` tells us git cinnabar generally
+/// hands us autoland dates/revs, it stands to reason some ignore lists will
+/// be authored in terms of hg revs too).
+fn start_cinnabar_hg2git_helper(git_repo: &Repository) -> Child {
+    Command::new("git")
+        .arg("cinnabar")
+        .arg("hg2git")
+        .arg("--batch")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .current_dir(git_repo.path())
+        .spawn()
+        .unwrap()
+}
+
+fn get_git_rev_from_hg(helper: &mut Child, hg_rev: &str) -> Option<Oid> {
+    write!(helper.stdin.as_mut().unwrap(), "{}\n", hg_rev).unwrap();
+    let mut reader = BufReader::new(helper.stdout.as_mut().unwrap());
+    let mut result = String::new();
+    reader.read_line(&mut result).unwrap();
+    let gitrev = result.trim();
+    if gitrev.chars().all(|c| c == '0') {
+        return None;
+    }
+    Oid::from_str(gitrev).ok()
+}
+
+/// Parses the `DIFF_ALGORITHM` env var (see `main`) into the `git2`
+/// algorithm it names, defaulting to `Histogram` -- unlike the library's own
+/// default of `Myers`, histogram (like patience) anchors on rarely-occurring
+/// lines, which tends to align hunk boundaries with how a human would have
+/// split the edit instead of fragmenting token runs into noisy small
+/// hunks, which matters to us because `ingest_diff_accumulating_deltas` and
+/// `infer_token_moves_from_diff_deltas` both work off of hunk/line
+/// boundaries rather than a semantic diff. Unrecognized values fall back to
+/// the default with a warning rather than aborting the import.
+fn parse_diff_algorithm(name: &str) -> DiffAlgorithm {
+    match name.to_ascii_lowercase().as_str() {
+        "myers" => DiffAlgorithm::Myers,
+        "minimal" => DiffAlgorithm::Minimal,
+        "patience" => DiffAlgorithm::Patience,
+        "histogram" => DiffAlgorithm::Histogram,
+        other => {
+            warn!("Unrecognized DIFF_ALGORITHM {:?}, falling back to histogram", other);
+            DiffAlgorithm::Histogram
+        }
+    }
+}
+
+/// Loads a `.git-blame-ignore-revs`-style file: one revision per line,
+/// blank lines and `#`-led comments ignored.  Each entry may be either a git
+/// SHA or an hg rev; hg revs are resolved to their git oid through
+/// `get_git_rev_from_hg` so that everywhere else in this tool (which walks
+/// the syntax/source repos by git oid) only has to check one `HashSet<Oid>`.
+/// Lines that don't resolve to anything we recognize are skipped with a
+/// warning rather than aborting the whole import over a stale entry.
+fn load_ignore_revs(path: &Path, git_repo: &Repository) -> HashSet<Oid> {
+    let mut ignore_revs = HashSet::new();
+
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) => {
+            warn!("Could not open ignore-revs file {:?}: {}", path, err);
+            return ignore_revs;
+        }
+    };
+
+    // Only spun up on demand since most ignore-revs files are pure git SHAs
+    // and won't need it at all.
+    let mut hg2git_helper: Option<Child> = None;
+
+    for line in BufReader::new(file).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => continue,
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Ok(oid) = Oid::from_str(line) {
+            if git_repo.find_commit(oid).is_ok() {
+                ignore_revs.insert(oid);
+                continue;
+            }
+        }
+
+        let helper = hg2git_helper.get_or_insert_with(|| start_cinnabar_hg2git_helper(git_repo));
+        match get_git_rev_from_hg(helper, line) {
+            Some(oid) => {
+                ignore_revs.insert(oid);
+            }
+            None => warn!("Ignore-revs entry {:?} did not resolve to a known git or hg revision", line),
+        }
+    }
+
+    ignore_revs
+}
+
 /// Starts the git-fast-import subcommand, to which data
 /// is fed for adding to the blame repo. Refer to
 /// https://git-scm.com/docs/git-fast-import for detailed
 /// documentation on git-fast-import.
-fn start_fast_import(git_repo: &Repository) -> Child {
+fn start_fast_import(git_repo: &Repository, marks_path: Option<&Path>) -> Child {
     // Note that we use the `--force` flag here, because there
     // are cases where the blame repo branch we're building was
     // initialized from some other branch (e.g. gecko-dev beta
@@ -62,15 +171,68 @@ fn start_fast_import(git_repo: &Repository) -> Child {
     // (for beta) the new branch head (beta) is not going to be a
     // a descendant of the original (master), and we need `--force`
     // to make git-fast-import allow that.
-    Command::new("git")
-        .arg("fast-import")
+    let mut cmd = Command::new("git");
+    cmd.arg("fast-import")
         .arg("--force")
         .arg("--quiet")
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
-        .current_dir(git_repo.path())
-        .spawn()
-        .unwrap()
+        .current_dir(git_repo.path());
+    // Only passed when RESUME=1 (see `main`): persisting marks to disk and
+    // reloading them on the next run is what lets a re-run's `Mark(n)`
+    // references line up with marks a previous, interrupted run already
+    // issued, instead of starting the numbering over from scratch.
+    if let Some(marks_path) = marks_path {
+        cmd.arg(format!("--export-marks={}", marks_path.display()));
+        cmd.arg(format!("--import-marks-if-exists={}", marks_path.display()));
+    }
+    cmd.spawn().unwrap()
+}
+
+/// Persisted companion to the blame ref, written alongside `rev_summary_root`
+/// and only used when `RESUME=1` (see `main`). The blame ref itself only
+/// reflects commits `git fast-import` has actually reached a `checkpoint` (or
+/// process exit) for, so a crash between checkpoints loses track of which
+/// marks were already issued and which source revisions they correspond to;
+/// this file -- flushed at the same cadence as the fast-import checkpoints --
+/// lets a re-run reconcile the two, the same role git-filter-repo's persisted
+/// id/rename map plays across its own runs.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ResumeState {
+    /// Next mark number to hand out. Continuing from here (rather than
+    /// restarting at 0) is what keeps a resumed run's `mark :<n>` lines from
+    /// colliding with marks a previous run already issued and that
+    /// `--import-marks-if-exists` will have reloaded into fast-import.
+    next_mark: usize,
+    /// Maps syntax-repo revision (hex oid) to its timeline commit, encoded
+    /// via `TimelineRepoCommit`'s `Display` impl (either `:<mark>` or a hex
+    /// oid) so it round-trips through `parse_timeline_repo_commit` below.
+    source_to_timeline: BTreeMap<String, String>,
+}
+
+fn parse_timeline_repo_commit(encoded: &str) -> Option<TimelineRepoCommit> {
+    match encoded.strip_prefix(':') {
+        Some(mark) => mark.parse().ok().map(TimelineRepoCommit::Mark),
+        None => Oid::from_str(encoded).ok().map(TimelineRepoCommit::Commit),
+    }
+}
+
+fn load_resume_state(path: &Path) -> Option<ResumeState> {
+    let file = File::open(path).ok()?;
+    serde_json::from_reader(BufReader::new(file)).ok()
+}
+
+fn write_resume_state(path: &Path, next_mark: usize, timeline_map: &HashMap<Oid, TimelineRepoCommit>) {
+    let state = ResumeState {
+        next_mark,
+        source_to_timeline: timeline_map
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+    };
+    let file = File::create(path).unwrap();
+    serde_json::to_writer(BufWriter::new(file), &state).unwrap();
 }
 
 /// When writing to a git-fast-import stream, we can insert temporary
@@ -164,6 +326,173 @@ fn read_path_blob(
     Some(blob)
 }
 
+/// Default bound on `BlobReadCache`'s resident entries; chosen to comfortably
+/// cover a revision's worth of directory traversal on large trees without
+/// letting memory grow unbounded on huge histories.
+const BLOB_READ_CACHE_CAPACITY: usize = 4096;
+
+/// `BlobReadCache` entries older than this are treated as a miss even if
+/// still resident, bounding how long we'll trust a cached lookup without
+/// bounding it purely on capacity.
+const BLOB_READ_CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// One cached `read_path_oid`/`read_path_blob` result pair for a given
+/// `(commit, path)` key. Both fields start `None` and get filled in
+/// independently since callers ask for the oid and the blob separately.
+struct BlobReadCacheEntry {
+    oid: Option<String>,
+    blob: Option<Vec<u8>>,
+    inserted_at: Instant,
+    /// The `recency` generation this entry was last touched at; see
+    /// `recency`'s doc comment for why this is what makes eviction O(1)
+    /// amortized instead of an O(n) scan.
+    generation: u64,
+}
+
+/// Bounded, time-boxed cache over the fast-import helper's `ls`/`cat-blob`
+/// round-trips, following the same time-to-live + max-capacity pattern web
+/// git viewers like rgit use around repeated object lookups. Keyed by a
+/// `TimelineRepoCommit`'s display string plus path. Since `TimelineRepoCommit`
+/// marks are assigned once per revision and never reused, and blob content at
+/// a given commit+path is immutable, a cached entry is never stale -- this
+/// exists purely to bound how much of the import helper's object graph we
+/// keep resident, not to invalidate anything.
+///
+/// Shared across the whole import (see `main`) rather than per-revision, so
+/// `build_blame_tree`'s sibling-file walk, `hyperblame_for_path`'s per-parent
+/// re-reads, and `write_token_author_rollups`'s directory lookups all draw
+/// from (and populate) the same pool of recently-seen objects.
+struct BlobReadCache {
+    capacity: usize,
+    ttl: Duration,
+    entries: HashMap<(String, PathBuf), BlobReadCacheEntry>,
+    /// (key, generation) pairs in touch order, oldest first. We never splice
+    /// out of the middle of this queue on a touch -- that would be the O(n)
+    /// scan we're trying to avoid -- so a key touched more than once ends up
+    /// with a stale earlier entry left behind here. An entry popped off the
+    /// front is only a real eviction candidate if its generation still
+    /// matches `entries[key].generation`; a TTL-expired or superseded-by-a-
+    /// later-touch entry is just discarded and the loop moves on to the next
+    /// front entry instead.
+    recency: VecDeque<(String, PathBuf, u64)>,
+    /// Next generation number to hand out; see `recency`.
+    next_generation: u64,
+}
+
+impl BlobReadCache {
+    fn new(capacity: usize, ttl: Duration) -> Self {
+        BlobReadCache {
+            capacity,
+            ttl,
+            entries: HashMap::new(),
+            recency: VecDeque::new(),
+            next_generation: 0,
+        }
+    }
+
+    /// Bumps `key`'s entry to the most-recently-used generation. Must only be
+    /// called once `entries` already has a live entry for `key`.
+    fn touch(&mut self, key: &(String, PathBuf)) {
+        self.next_generation += 1;
+        let generation = self.next_generation;
+        self.entries.get_mut(key).unwrap().generation = generation;
+        self.recency.push_back((key.0.clone(), key.1.clone(), generation));
+    }
+
+    /// Returns the live entry for `key`, evicting it first if its TTL has
+    /// expired. `None` means a cache miss -- the caller is expected to fetch
+    /// the real value and feed it back through `entry`.
+    fn get(&mut self, key: &(String, PathBuf)) -> Option<&BlobReadCacheEntry> {
+        let expired = self.entries.get(key)?.inserted_at.elapsed() > self.ttl;
+        if expired {
+            // Leaving this key's stale (key, generation) pairs in `recency`
+            // is fine: `entries` no longer has a matching generation for any
+            // of them, so `entry()`'s eviction loop below will just skip
+            // past them as it would any other superseded touch.
+            self.entries.remove(key);
+            return None;
+        }
+        self.touch(key);
+        self.entries.get(key)
+    }
+
+    /// Returns the (possibly freshly-inserted) entry for `key` for a caller
+    /// to fill in, evicting the least-recently-used entry first if we're
+    /// about to exceed `capacity`.
+    fn entry(&mut self, key: (String, PathBuf)) -> &mut BlobReadCacheEntry {
+        if !self.entries.contains_key(&key) {
+            while self.entries.len() >= self.capacity {
+                let Some((oldest_key, oldest_path, oldest_generation)) = self.recency.pop_front() else {
+                    // Every entry is somehow already a live generation match
+                    // but `recency` ran dry first (shouldn't happen since
+                    // every insert is immediately touched); bail rather than
+                    // loop forever.
+                    break;
+                };
+                let oldest = (oldest_key, oldest_path);
+                if self.entries.get(&oldest).map(|e| e.generation) == Some(oldest_generation) {
+                    self.entries.remove(&oldest);
+                    break;
+                }
+                // Stale pair left behind by an intervening touch or TTL
+                // eviction -- not a real eviction candidate, keep looking.
+            }
+            self.entries.insert(
+                key.clone(),
+                BlobReadCacheEntry {
+                    oid: None,
+                    blob: None,
+                    inserted_at: Instant::now(),
+                    generation: 0,
+                },
+            );
+        }
+        self.touch(&key);
+        self.entries.get_mut(&key).unwrap()
+    }
+}
+
+/// `read_path_oid`, but consulting/populating `cache` first so repeated
+/// lookups of the same `(commit, path)` don't round-trip to the fast-import
+/// helper more than once while the entry stays cached. A lookup that found
+/// nothing is stored as `None`, which counts as a miss, so it is fetched
+/// again on the next call.
+fn cached_read_path_oid(
+    cache: &mut BlobReadCache,
+    import_helper: &mut Child,
+    commit: &TimelineRepoCommit,
+    path: &Path,
+) -> Option<String> {
+    let key = (commit.to_string(), path.to_path_buf());
+    if let Some(entry) = cache.get(&key) {
+        if entry.oid.is_some() {
+            return entry.oid.clone();
+        }
+    }
+    let oid = read_path_oid(import_helper, commit, path);
+    cache.entry(key).oid = oid.clone();
+    oid
+}
+
+/// `read_path_blob`, but consulting/populating `cache` first; see
+/// `cached_read_path_oid`.
+fn cached_read_path_blob(
+    cache: &mut BlobReadCache,
+    import_helper: &mut Child,
+    commit: &TimelineRepoCommit,
+    path: &Path,
+) -> Option<Vec<u8>> {
+    let key = (commit.to_string(), path.to_path_buf());
+    if let Some(entry) = cache.get(&key) {
+        if entry.blob.is_some() {
+            return entry.blob.clone();
+        }
+    }
+    let blob = read_path_blob(import_helper, commit, path);
+    cache.entry(key).blob = blob.clone();
+    blob
+}
+
 /// Sanitizes a path into a format that git-fast-import wants.
 fn sanitize(path: &Path) -> std::borrow::Cow<str> {
     // Technically, I'm not sure what git-fast-import expects to happen with
@@ -199,8 +528,7 @@ fn test_sanitize() {
     assert_eq!(sanitize(&p4), "\"internal/lf/\\\n/needs/escaping\"");
 }
 
-fn count_lines(blob: &git2::Blob) -> usize {
-    let data = blob.content();
+fn count_lines(data: &[u8]) -> usize {
     if data.is_empty() {
         return 0;
     }
@@ -216,21 +544,58 @@ fn count_lines(blob: &git2::Blob) -> usize {
     linecount
 }
 
-/// Given a blob and its parent, derive the diff and process its hunks in order
-/// to produce the set of unmodified token (line indices) as well as the
-/// removals and additions so we can infer token moves in a subsequent pass once
-/// we've run this logic across all patches.
+/// One unit of diff work collected during the (single-threaded)
+/// `process_tree_changes` walk and handed off to a worker in
+/// `ingest_diff_work_items_parallel`.  We can't hand a `git2::Blob` itself to
+/// another thread -- it borrows from the `Repository` it came from, which
+/// isn't `Sync` -- so we pull the owned bytes out while we still have the
+/// blob in hand and carry those across instead.
+struct DiffWorkItem {
+    /// The parent commit this diff is against; together with `path` this is
+    /// the key `unmodified_tokens` is stored under (see `TimelineData`'s
+    /// field doc for why the parent commit id, not just the path, has to be
+    /// part of the key).
+    parent_rev: Oid,
+    path: PathBuf,
+    namespace: &'static str,
+    parent_content: Vec<u8>,
+    content: Vec<u8>,
+    /// Whether `parent_rev` is `commit.parent(0)` -- the mainline lineage for
+    /// a merge. `unmodified_tokens` is still computed against every parent
+    /// (that's what lets `hyperblame_for_path` pick the right per-line
+    /// provenance across a merge's lineages), but only the mainline parent's
+    /// diff feeds `DeltaMachine`/`TokenStatsMachine`/`RevisionTokenChurn`;
+    /// otherwise a merge would walk the same hunk of real authorship twice,
+    /// once per parent, and double-count it as churn. For a non-merge commit
+    /// this is always true, since its one parent is trivially the mainline.
+    is_primary_parent: bool,
+}
+
+/// Given a blob and its parent's content, derive the diff and process its
+/// hunks in order to produce the set of unmodified token (line indices) as
+/// well as the removals and additions so we can infer token moves in a
+/// subsequent pass once we've run this logic across all patches.
 ///
-/// This method could potentially be naively parallelized.
+/// Diffs from owned buffers rather than `git2::Blob`s so this can run inside
+/// `ingest_diff_work_items_parallel`'s worker threads, which don't have
+/// access to the `Repository` the blobs were originally read from.
 fn ingest_diff_accumulating_deltas(
-    blob: &git2::Blob,
-    parent_blob: &git2::Blob,
-    path: &Path,
+    parent_content: &[u8],
+    content: &[u8],
+    linecount: usize,
+    namespace: &'static str,
+    path: &str,
+    diff_algorithm: DiffAlgorithm,
     delter: &mut DeltaMachine,
+    token_stats: &mut TokenStatsMachine,
+    churn: &mut RevisionTokenChurn,
+    is_primary_parent: bool,
 ) -> Result<Vec<(usize, usize)>, git2::Error> {
     let mut unchanged = Vec::new();
 
-    let patch = Patch::from_blobs(parent_blob, None, blob, None, None)?;
+    let mut diff_opts = DiffOptions::new();
+    diff_opts.diff_algorithm(diff_algorithm);
+    let patch = Patch::from_buffers(parent_content, None, content, None, Some(&mut diff_opts))?;
 
     if patch.delta().flags().is_binary() {
         return Ok(unchanged);
@@ -243,7 +608,7 @@ fn ingest_diff_accumulating_deltas(
     let mut latest_line: usize = 0;
     let mut delta: i32 = 0;
 
-    let namespace = namespace_for_file(path);
+    delter.set_namespace(namespace);
 
     for hunk_index in 0..patch.num_hunks() {
         for line_index in 0..patch.num_lines_in_hunk(hunk_index)? {
@@ -257,8 +622,28 @@ fn ingest_diff_accumulating_deltas(
                 latest_line = (lineno - 1) + 1;
             }
 
-            if let Some((context, token)) = from_utf8(line.content()).unwrap().split_once(' ') {
-                delter.push_diff_token(&line, context, token);
+            // A merge's non-mainline parents only contribute to `unchanged`
+            // below (so `hyperblame_for_path` can still credit a line to that
+            // lineage) -- feeding their diffs into `delter`/`token_stats`/
+            // `churn` too would double-count every hunk of real authorship
+            // once per parent it happens to also differ from. See
+            // `DiffWorkItem::is_primary_parent`.
+            if is_primary_parent {
+                if let Some((context, token)) = from_utf8(line.content()).unwrap().split_once(' ') {
+                    delter.push_diff_token(&line, context, token);
+                    token_stats.record_token(line.origin(), path, token);
+                    // `RevisionTokenChurn` only cares about the line number on
+                    // whichever side the token actually lives on: the new-file
+                    // line for an addition, the old-file line for a removal.
+                    let churn_lineno = match line.origin() {
+                        '+' => line.new_lineno(),
+                        '-' => line.old_lineno(),
+                        _ => None,
+                    };
+                    if let Some(churn_lineno) = churn_lineno {
+                        churn.push(line.origin(), namespace, token, Path::new(path), churn_lineno);
+                    }
+                }
             }
 
             match line.origin() {
@@ -285,17 +670,103 @@ fn ingest_diff_accumulating_deltas(
         delter.flush_hunk();
     }
 
-    let linecount = count_lines(blob);
     for i in latest_line..linecount {
         unchanged.push((i, add_delta(i, delta)));
     }
     Ok(unchanged)
 }
 
+/// Where a single added or removed token line occurred, tagged with the
+/// semantic namespace its file was tokenized under and the literal token
+/// text, so `infer_token_moves_from_diff_deltas` can index and match on
+/// `(namespace, token)` without re-deriving either.
+#[derive(Clone, Debug)]
+struct TokenOccurrence {
+    namespace: &'static str,
+    token: String,
+    path: PathBuf,
+    lineno: u32,
+}
+
+/// A single location a token was added to or removed from, as exposed in
+/// `infer_token_moves_from_diff_deltas`'s result map.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct TokenLineRef {
+    path: PathBuf,
+    lineno: u32,
+}
+
+/// Flat, revision-scoped record of every token added or removed across all
+/// files touched by a revision.  Much simpler than `DeltaMachine`'s
+/// per-context run tracking: this doesn't care about scope continuity, only
+/// "where did this exact token disappear/appear", which is all move/copy
+/// detection across files needs.
+#[derive(Default)]
+struct RevisionTokenChurn {
+    removed: Vec<TokenOccurrence>,
+    added: Vec<TokenOccurrence>,
+}
+
+impl RevisionTokenChurn {
+    fn push(&mut self, origin: char, namespace: &'static str, token: &str, path: &Path, lineno: u32) {
+        let occurrence = TokenOccurrence {
+            namespace,
+            token: token.to_string(),
+            path: path.to_path_buf(),
+            lineno,
+        };
+        match origin {
+            '+' => self.added.push(occurrence),
+            '-' => self.removed.push(occurrence),
+            _ => (),
+        }
+    }
+
+    /// Folds another worker's partial churn into this one; see
+    /// `ingest_diff_work_items_parallel`, which gives each worker its own
+    /// `RevisionTokenChurn` the same way it does for `DeltaMachine`/
+    /// `TokenStatsMachine`.
+    fn merge(&mut self, other: RevisionTokenChurn) {
+        self.added.extend(other.added);
+        self.removed.extend(other.removed);
+    }
+
+    /// Sorts `added`/`removed` by `(path, lineno)` so that
+    /// `infer_token_moves_from_diff_deltas`'s greedy, index-order-sensitive
+    /// matching doesn't depend on which worker thread happened to finish
+    /// diffing a given file first.
+    fn sort_for_determinism(&mut self) {
+        self.added.sort_by(|a, b| (&a.path, a.lineno).cmp(&(&b.path, b.lineno)));
+        self.removed.sort_by(|a, b| (&a.path, a.lineno).cmp(&(&b.path, b.lineno)));
+    }
+}
+
+/// Revisions whose total add+remove token churn exceeds this are skipped for
+/// move inference entirely.  Whole-tree mechanical reformats (clang-format
+/// re-runs, `.ini` -> `.toml` test manifest conversions, etc.) produce an
+/// enormous number of lines that superficially look "moved" without
+/// representing any real authorship movement, and the index-then-scan below
+/// isn't worth paying for them.
+const MOVE_INFERENCE_MAX_CHURN: usize = 50_000;
+
+/// Tokens occurring more than this many times among a revision's removed set
+/// are dropped from the match index before scanning, so ubiquitous tokens
+/// like `}` or `return` can't anchor spurious matches.
+const MOVE_INFERENCE_TOKEN_FREQUENCY_CAP: usize = 32;
+
 /// Consumes the aggregated output of all `ingest_diff_accumulating_deltas`
 /// processing of the patches in order to detect token movement leveraging their
 /// semantic binding.
 ///
+/// This is git-blame `-M`/`-C`-style move/copy detection: build an index
+/// mapping each removed line's `(namespace, token)` key to every place it was
+/// removed in this revision, then for each added line look up candidate
+/// origins in that index and greedily extend the match forward to find the
+/// longest contiguous run of adjacent added/removed lines that keep
+/// matching, so a whole moved block gets attributed to one source rather
+/// than line-by-line noise. Each removed line is consumed by at most one
+/// match.
+///
 /// This method could potentially be parallelized based naively based on a
 /// language basis first since it's likely pointless to try and bother deriving
 /// the history of a JS implementation of something being written into C++, it
@@ -307,8 +778,101 @@ fn ingest_diff_accumulating_deltas(
 /// cases where there are an overwhelming number of changes or where a simple
 /// top-N histogram of impacted tokens does not satisfy simple rename
 /// heuristics.
-fn infer_token_moves_from_diff_deltas {
+fn infer_token_moves_from_diff_deltas(churn: &RevisionTokenChurn) -> HashMap<TokenLineRef, TokenLineRef> {
+    let mut result = HashMap::new();
+
+    if churn.added.len() + churn.removed.len() > MOVE_INFERENCE_MAX_CHURN {
+        return result;
+    }
+
+    let mut by_key: HashMap<(&'static str, &str), Vec<usize>> = HashMap::new();
+    for (i, occ) in churn.removed.iter().enumerate() {
+        by_key
+            .entry((occ.namespace, occ.token.as_str()))
+            .or_insert_with(Vec::new)
+            .push(i);
+    }
+    by_key.retain(|_, indices| indices.len() <= MOVE_INFERENCE_TOKEN_FREQUENCY_CAP);
 
+    let mut consumed_removed = vec![false; churn.removed.len()];
+
+    let mut added_index = 0;
+    while added_index < churn.added.len() {
+        let added = &churn.added[added_index];
+        let candidates = match by_key.get(&(added.namespace, added.token.as_str())) {
+            Some(candidates) => candidates,
+            None => {
+                added_index += 1;
+                continue;
+            }
+        };
+
+        // Prefer whichever unconsumed candidate lets us extend the longest
+        // contiguous run; ties broken toward the earlier (lower index)
+        // candidate for determinism.
+        let mut best: Option<(usize, usize)> = None;
+        for &removed_index in candidates {
+            if consumed_removed[removed_index] {
+                continue;
+            }
+            let run_len = contiguous_run_len(churn, added_index, removed_index, &consumed_removed);
+            if best.map_or(true, |(_, best_len)| run_len > best_len) {
+                best = Some((removed_index, run_len));
+            }
+        }
+
+        let Some((removed_index, run_len)) = best else {
+            added_index += 1;
+            continue;
+        };
+
+        for offset in 0..run_len {
+            let a = &churn.added[added_index + offset];
+            let r = &churn.removed[removed_index + offset];
+            consumed_removed[removed_index + offset] = true;
+            result.insert(
+                TokenLineRef {
+                    path: a.path.clone(),
+                    lineno: a.lineno,
+                },
+                TokenLineRef {
+                    path: r.path.clone(),
+                    lineno: r.lineno,
+                },
+            );
+        }
+        added_index += run_len;
+    }
+
+    result
+}
+
+/// Greedily extends the match starting at `(added_start, removed_start)`
+/// forward as long as the next added/removed pair keep sharing a
+/// `(namespace, token)` key and the removed side isn't already consumed by
+/// an earlier, longer run. Always at least 1, since the caller only invokes
+/// this for an already-matching starting pair.
+fn contiguous_run_len(
+    churn: &RevisionTokenChurn,
+    added_start: usize,
+    removed_start: usize,
+    consumed_removed: &[bool],
+) -> usize {
+    let mut len = 0;
+    loop {
+        let a_idx = added_start + len;
+        let r_idx = removed_start + len;
+        if a_idx >= churn.added.len() || r_idx >= churn.removed.len() || consumed_removed[r_idx] {
+            break;
+        }
+        let a = &churn.added[a_idx];
+        let r = &churn.removed[r_idx];
+        if a.namespace != r.namespace || a.token != r.token {
+            break;
+        }
+        len += 1;
+    }
+    len.max(1)
 }
 
 ///
@@ -318,21 +882,32 @@ fn hyperblame_for_path(
     commit: &git2::Commit,
     blob: &git2::Blob,
     import_helper: &mut Child,
+    cache: &mut BlobReadCache,
     blame_parents: &[TimelineRepoCommit],
     path: &Path,
 ) -> Result<String, git2::Error> {
-    let linecount = count_lines(&blob);
+    let linecount = count_lines(blob.content());
     let mut line_data = LineData {
         rev: Cow::Owned(commit.id().to_string()),
         path: LineData::path_unchanged(),
         lineno: Cow::Owned(String::new()),
     };
     let mut blame = Vec::with_capacity(linecount);
+    // Tracks which lines still hold the "introduced by this commit" blame we
+    // just seeded above, as opposed to having inherited parent blame below.
+    // Consulted by the `token_moves` pass below and, when `commit` is in
+    // `diff_data.ignore_revs`, by `inherit_ignored_commit_lines`.
+    let mut introduced_here = vec![true; linecount];
     for line in 1..=linecount {
         line_data.lineno = Cow::Owned(line.to_string());
         blame.push(line_data.serialize());
     }
 
+    // Walk every parent -- not just the mainline -- so a merge's per-line
+    // provenance is reconciled across both lineages rather than reset to the
+    // merge commit itself: `.rev()` visits the mainline parent (index 0)
+    // *last*, so if a line is unmodified relative to both the mainline and a
+    // secondary parent, the mainline's attribution is what sticks.
     for (parent, blame_parent) in commit.parents().zip(blame_parents.iter()).rev() {
         let parent_path = diff_data
             .file_movement
@@ -347,7 +922,7 @@ fn hyperblame_for_path(
             Some(entry) => entry,
             _ => continue,
         };
-        let parent_annotate_blob = match read_path_blob(import_helper, blame_parent, parent_path) {
+        let parent_annotate_blob = match cached_read_path_blob(cache, import_helper, blame_parent, parent_path) {
             Some(blob) => blob,
             _ => continue,
         };
@@ -358,6 +933,7 @@ fn hyperblame_for_path(
 
         let path_unchanged = path == parent_path;
         for (lineno, parent_lineno) in unmodified_lines {
+            introduced_here[*lineno] = false;
             if path_unchanged {
                 blame[*lineno] = String::from(parent_blame[*parent_lineno]);
                 continue;
@@ -368,12 +944,205 @@ fn hyperblame_for_path(
             }
             blame[*lineno] = line_data.serialize();
         }
+
+        // Any line this parent's diff didn't mark unmodified is either a
+        // genuine first introduction or a cross-file move/copy that
+        // `infer_token_moves_from_diff_deltas` caught; consult
+        // `token_moves` for the latter so the blame follows the tokens
+        // instead of crediting this commit with authoring lines it only
+        // relocated.
+        for lineno in 0..linecount {
+            if !introduced_here[lineno] {
+                continue;
+            }
+            let dest = TokenLineRef {
+                path: path.to_path_buf(),
+                lineno: (lineno + 1) as u32,
+            };
+            let Some(src) = diff_data.token_moves.get(&dest) else {
+                continue;
+            };
+            // Mirror the unmodified-lines resolution above: if the source
+            // file was itself renamed by this revision's file-movement
+            // detection, read its blame under its *parent* path rather than
+            // assuming the name held steady.
+            let src_parent_path = commit
+                .tree()
+                .ok()
+                .and_then(|tree| tree.get_path(&src.path).ok())
+                .and_then(|entry| {
+                    diff_data
+                        .file_movement
+                        .as_ref()
+                        .and_then(|m| m.get(&entry.id()))
+                        .cloned()
+                })
+                .unwrap_or_else(|| src.path.clone());
+            let src_annotate_blob =
+                match cached_read_path_blob(cache, import_helper, blame_parent, &src_parent_path) {
+                    Some(blob) => blob,
+                    _ => continue,
+                };
+            let src_blame_text = std::str::from_utf8(&src_annotate_blob).unwrap();
+            let src_lines: Vec<&str> = src_blame_text.lines().collect();
+            let src_idx = match (src.lineno as usize).checked_sub(1) {
+                Some(idx) if idx < src_lines.len() => idx,
+                _ => continue,
+            };
+            introduced_here[lineno] = false;
+            let src_path_unchanged = path == src.path;
+            if src_path_unchanged {
+                blame[lineno] = String::from(src_lines[src_idx]);
+                continue;
+            }
+            let mut line_data = LineData::deserialize(src_lines[src_idx]);
+            if line_data.is_path_unchanged() {
+                line_data.path = Cow::Borrowed(src.path.to_str().unwrap());
+            }
+            blame[lineno] = line_data.serialize();
+        }
     }
+
+    // `commit` is on the ignore-revs list (mirrors `git blame --ignore-rev` /
+    // `.git-blame-ignore-revs`): don't let any *modified* line keep pointing
+    // at this commit either. We have no diff-derived parent origin for a
+    // modified line (that's the whole reason it's modified), so best-effort
+    // it by borrowing whichever already-resolved neighboring line (preceding,
+    // falling back to following) inherited its blame from a parent above.
+    if diff_data.ignore_revs.contains(&commit.id()) {
+        inherit_ignored_commit_lines(&mut blame, &introduced_here);
+    }
+
     // Extra entry so the `join` call after adds a trailing newline
     blame.push(String::new());
     Ok(blame.join("\n"))
 }
 
+/// Best-effort pass run only for commits on the ignore-revs list: every line
+/// still flagged `introduced_here` (i.e. it inherited from neither a parent's
+/// `unmodified_tokens` nor a `token_moves` source, so it would otherwise be
+/// blamed on this ignored commit) borrows the blame of the nearest preceding
+/// line that did inherit, or failing that the nearest following one, so a
+/// whole-file reformat commit doesn't mask the
+/// pre-reformat authorship of the lines it touched.
+fn inherit_ignored_commit_lines(blame: &mut [String], introduced_here: &[bool]) {
+    let n = blame.len();
+    for i in 0..n {
+        if !introduced_here[i] {
+            continue;
+        }
+        let preceding = (0..i).rev().find(|&j| !introduced_here[j]);
+        let following = (i + 1..n).find(|&j| !introduced_here[j]);
+        if let Some(source) = preceding.or(following) {
+            blame[i] = blame[source].clone();
+        }
+        // If every line in the file is new under this ignored commit, there
+        // is nothing to inherit from, so we leave the line attributed to it.
+    }
+}
+
+/// Resolves a commit's author `git2::Signature` through `.mailmap` (see
+/// `main`'s one-time `Repository::mailmap` load), falling back to the raw
+/// "Name <email>" pair if no mailmap was loaded or it doesn't have an entry
+/// for this identity, so missing mailmap coverage degrades to today's
+/// uncanonicalized behavior instead of aborting the import.
+fn canonical_author_identity(mailmap: Option<&git2::Mailmap>, sig: &git2::Signature) -> String {
+    let resolved = mailmap.and_then(|m| m.resolve_signature(sig).ok());
+    let sig = resolved.as_ref().unwrap_or(sig);
+    format!("{} <{}>", sig.name().unwrap_or("?"), sig.email().unwrap_or("?"))
+}
+
+/// Reads and deserializes the `AuthorTokenRollupRecord` at `path` from the
+/// first (mainline) blame parent, mirroring `hyperblame_for_path`'s use of
+/// `read_path_blob` to pull prior state out of the timeline repo rather than
+/// keeping it resident in memory across the whole import. Returns an empty
+/// record if `path` has no prior entry (a new file/directory) or only has
+/// additional parents (merge commits): see `write_token_author_rollups` for
+/// why we don't attempt to combine multiple parents' rollups here yet.
+fn read_author_rollup(
+    import_helper: &mut Child,
+    cache: &mut BlobReadCache,
+    blame_parents: &[TimelineRepoCommit],
+    path: &Path,
+) -> AuthorTokenRollupRecord {
+    let Some(first_parent) = blame_parents.first() else {
+        return AuthorTokenRollupRecord::empty();
+    };
+    match cached_read_path_blob(cache, import_helper, first_parent, path) {
+        Some(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|_| AuthorTokenRollupRecord::empty()),
+        None => AuthorTokenRollupRecord::empty(),
+    }
+}
+
+/// Serializes `record` and writes it inline at `path` for the commit
+/// currently open on `import_helper`'s stdin, the same inline-data dance
+/// `build_blame_tree` uses for blame blobs.
+fn write_author_rollup(import_helper: &mut Child, path: &Path, record: &AuthorTokenRollupRecord) {
+    let bytes = serde_json::to_vec(record).unwrap();
+    let import_stream = import_helper.stdin.as_mut().unwrap();
+    write!(import_stream, "M 100644 inline {}\n", sanitize(path)).unwrap();
+    write!(import_stream, "data {}\n", bytes.len()).unwrap();
+    import_stream.write(&bytes).unwrap();
+}
+
+/// Folds this revision's per-file token totals into the cumulative
+/// token-weighted `git shortlog` kept under `token-authors/<path>.json`
+/// (one record per source file) and `token-authors-by-dir/<dir>/_dir.json`
+/// (one record per ancestor directory, merging every touched file beneath
+/// it), attributing the whole revision to `author`.
+///
+/// Like `tokens/` (see `timeline_tokens`), neither of these directories is
+/// ever `D`eleted in `main`, so untouched paths simply carry their rollup
+/// forward unchanged from the parent commit; we only need to read-modify-
+/// write the paths this revision actually touched.
+///
+/// For merge commits we only read/accumulate against the first (mainline)
+/// blame parent, same as `main` does when it writes the commit's `from` line.
+/// This under-counts a merge's non-mainline side's token churn rather than
+/// double-counting it against both parents' histories; proper multi-parent
+/// provenance is tracked as follow-up work.
+fn write_token_author_rollups(
+    import_helper: &mut Child,
+    cache: &mut BlobReadCache,
+    blame_parents: &[TimelineRepoCommit],
+    author: &str,
+    file_totals: &BTreeMap<PathBuf, TokenDeltaDetails>,
+) {
+    let mut dir_totals: BTreeMap<PathBuf, TokenDeltaDetails> = BTreeMap::new();
+
+    for (path, delta) in file_totals {
+        let rollup_path = Path::new("token-authors").join(path).with_extension("json");
+        let mut record = read_author_rollup(import_helper, cache, blame_parents, &rollup_path);
+        record.accumulate(author.to_string(), delta);
+        write_author_rollup(import_helper, &rollup_path, &record);
+
+        let mut dir = path.parent();
+        while let Some(d) = dir {
+            let entry = dir_totals.entry(d.to_path_buf()).or_insert(TokenDeltaDetails {
+                added: 0,
+                moved: 0,
+                evolved_from: 0,
+                removed: 0,
+            });
+            entry.added += delta.added;
+            entry.moved += delta.moved;
+            entry.evolved_from += delta.evolved_from;
+            entry.removed += delta.removed;
+            if d.as_os_str().is_empty() {
+                break;
+            }
+            dir = d.parent();
+        }
+    }
+
+    for (dir, delta) in &dir_totals {
+        let rollup_path = Path::new("token-authors-by-dir").join(dir).join("_dir.json");
+        let mut record = read_author_rollup(import_helper, cache, blame_parents, &rollup_path);
+        record.accumulate(author.to_string(), delta);
+        write_author_rollup(import_helper, &rollup_path, &record);
+    }
+}
+
 // Helper that recursively walks the tree for the given commit, skipping over
 // unmodified entries.  When modified blobs are encountered, the provided
 // `handler` is invoked.
@@ -399,7 +1168,7 @@ fn process_tree_changes(
     git_repo: &git2::Repository,
     commit: &git2::Commit,
     mut path: PathBuf,
-    handler: &mut dyn FnMut(&Blob, &Blob, &Path)
+    handler: &mut dyn FnMut(&Blob, &Blob, Oid, &Path, bool)
 ) -> Result<(), git2::Error> {
     let tree_at_path = if path == PathBuf::new() {
         commit.tree()?
@@ -410,6 +1179,13 @@ fn process_tree_changes(
             .to_object(git_repo)?
             .peel_to_tree()?
     };
+    // Diffing a merge against every parent gives us a per-parent candidate
+    // provenance for each line (see `DiffWorkItem::parent_rev`'s doc), but
+    // the mainline (first) parent is the only one whose diff should feed the
+    // structural `DeltaMachine`/`RevisionTokenChurn` machinery -- see the
+    // `is_primary_parent` argument `handler` receives below, and its use at
+    // `thread_preprocess_revision`'s call site.
+    let mainline_parent_id = commit.parent(0).ok().map(|p| p.id());
     'outer: for entry in tree_at_path.iter() {
         path.push(entry.name().unwrap());
         for parent in commit.parents() {
@@ -435,7 +1211,8 @@ fn process_tree_changes(
                         _ => continue,
                     };
 
-                    handler(&blob, &parent_blob, &path);
+                    let is_primary_parent = Some(parent.id()) == mainline_parent_id;
+                    handler(&blob, &parent_blob, parent.id(), &path, is_primary_parent);
                 }
             }
             Some(ObjectType::Tree) => {
@@ -457,6 +1234,7 @@ fn build_blame_tree(
     tree_at_path: &git2::Tree,
     parent_trees: &[Option<git2::Tree>],
     import_helper: &mut Child,
+    cache: &mut BlobReadCache,
     blame_parents: &[TimelineRepoCommit],
     mut path: PathBuf,
 ) -> Result<(), git2::Error> {
@@ -472,7 +1250,7 @@ fn build_blame_tree(
                 if parent_entry.id() == entry.id() {
                     // Item at `path` is the same in the tree for `commit` as in
                     // `parent_trees[i]`, so the blame must be the same too
-                    let oid = read_path_oid(import_helper, &blame_parents[i], &path).unwrap();
+                    let oid = cached_read_path_oid(cache, import_helper, &blame_parents[i], &path).unwrap();
                     write!(
                         import_helper.stdin.as_mut().unwrap(),
                         "M {:06o} {} {}\n",
@@ -494,6 +1272,7 @@ fn build_blame_tree(
                     commit,
                     &entry.to_object(git_repo)?.peel_to_blob()?,
                     import_helper,
+                    cache,
                     blame_parents,
                     &path,
                 )?;
@@ -559,6 +1338,7 @@ fn build_blame_tree(
                     &entry.to_object(git_repo)?.peel_to_tree()?,
                     &parent_subtrees,
                     import_helper,
+                    cache,
                     blame_parents,
                     path.clone(),
                 )?;
@@ -588,8 +1368,11 @@ struct TimelineData {
     syntax_rev: git2::Oid,
 
     // Map from file (blob) id in the child rev to the path that the file was
-    // at in the parent revision, for files that got moved. Set to None if the
-    // child rev has multiple parents.
+    // at in the parent revision, for files that got moved. Diffed against
+    // the mainline (first) parent even for merge commits -- see
+    // `thread_preprocess_revision`'s comment on why that's the right lineage
+    // to resolve renames against. `None` only for the very first commit in
+    // the syntax repo, which has no parent to diff against at all.
     file_movement: Option<HashMap<Oid, PathBuf>>,
     // Map to find unmodified tokens for modified files in a revision (files that
     // are not modified don't have entries here). The key is of the map is a
@@ -600,19 +1383,180 @@ struct TimelineData {
     // The value in the map is a vec of token mappings as produced by the
     // `unmodified_tokens` function.
     unmodified_tokens: HashMap<(git2::Oid, PathBuf), Vec<(usize, usize)>>,
+
+    // Per-file token add/remove totals for this revision, collapsed from
+    // `TokenStatsMachine::file_token_deltas` by `TokenStatsMachine::file_totals`.
+    // Consumed by `write_token_author_rollups` in `main`, which attributes the
+    // whole total to the revision's single (mailmap-canonicalized) author.
+    token_file_totals: BTreeMap<PathBuf, TokenDeltaDetails>,
+
+    // Cross-file token move/copy destinations inferred by
+    // `infer_token_moves_from_diff_deltas` over every patch this revision's
+    // worker pool ingested (see `RevisionTokenChurn`). Keyed by the
+    // destination occurrence (this revision's path/line), valued by the
+    // source occurrence the tokens on that line most likely came from.
+    // Consulted by `hyperblame_for_path` to carry blame across the move
+    // instead of attributing the line to this revision.
+    token_moves: HashMap<TokenLineRef, TokenLineRef>,
+
+    // Shared across every revision (loaded once in `main` from
+    // `IGNORE_REVS_FILE`), so cheaply `Arc`-cloned rather than recomputed per
+    // revision. Consulted by `hyperblame_for_path` to pass mechanical
+    // commits' blame through to the real underlying author; see
+    // `load_ignore_revs`.
+    ignore_revs: Arc<HashSet<Oid>>,
+
+    // Wall-clock cost of this revision's major `thread_preprocess_revision`
+    // phases, folded into `PipelineStats` by `main` and optionally written
+    // out to `STATS_FILE`.
+    phase_timings: PhaseTimings,
 }
 
-/// Accumulates raw token statistics for a single revision.
-///
+/// Per-revision wall-clock timings for `thread_preprocess_revision`'s major
+/// phases, in whole microseconds (cheap to serialize and sum; sub-microsecond
+/// precision isn't meaningful at per-revision granularity). Modeled on the
+/// `--stats` timing breakdown the pijul git importer prints, but accumulated
+/// across every revision into `PipelineStats` rather than printed per-commit.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+struct PhaseTimings {
+    /// `diff_find_similar`-based file movement detection against the
+    /// mainline parent's "files" tree.
+    file_movement_us: u64,
+    /// Walking "files" tree changes and fanning them out across
+    /// `ingest_diff_work_items_parallel`'s worker pool.
+    diff_ingest_us: u64,
+    /// `DeltaContextCluster::infer_moves` across every namespace/context
+    /// cluster touched by this revision.
+    infer_moves_us: u64,
+}
+
+/// Accumulated `PhaseTimings` across however many revisions `main` has
+/// processed so far, plus the count they were summed over so an average is
+/// one division away. Written to `STATS_FILE` (if set) at the same
+/// checkpoint cadence as `ResumeState`, and once more at clean exit.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PipelineStats {
+    revisions: u64,
+    total_file_movement_us: u64,
+    total_diff_ingest_us: u64,
+    total_infer_moves_us: u64,
+}
+
+impl PipelineStats {
+    fn accumulate(&mut self, timings: &PhaseTimings) {
+        self.revisions += 1;
+        self.total_file_movement_us += timings.file_movement_us;
+        self.total_diff_ingest_us += timings.diff_ingest_us;
+        self.total_infer_moves_us += timings.infer_moves_us;
+    }
+}
+
+fn write_pipeline_stats(path: &Path, stats: &PipelineStats) {
+    let file = File::create(path).unwrap();
+    serde_json::to_writer_pretty(BufWriter::new(file), stats).unwrap();
+}
+
+/// Accumulates raw token statistics for a single revision, driven by
+/// `ingest_diff_accumulating_deltas` the same way `DeltaMachine` is, but
+/// without caring about scope/context continuity: all it needs per line is
+/// "was this token added or removed, and in which file", which is exactly
+/// what `write_token_author_rollups` needs to fold into the per-author
+/// rollup once the revision's commit (and hence its author) is known.
 ///
+/// `moved`/`evolved_from` are left at 0 here; distinguishing a plain
+/// add/remove from a move or evolution requires the cross-file inference
+/// `infer_token_moves_from_diff_deltas` (and eventually
+/// `DeltaContextCluster::infer_moves`) do, which this machine doesn't
+/// attempt to fold in yet.
 struct TokenStatsMachine {
-    /// Stats for each token across the whole revision.
-    revision_token_deltas: BTreeMap<String, TokenDelta>,
+    /// Stats for each token across the whole revision, intended to
+    /// eventually back the per-token `tokens/ab/cd` cross-reference files
+    /// documented in `timeline_tokens`; nothing reads this yet.
+    revision_token_deltas: BTreeMap<String, TokenDeltaDetails>,
 
     /// Map from file to token to deltas for that token in that file.
-    file_token_deltas: BTreeMap<String, BTreeMap<String, TokenDelta>>,
+    file_token_deltas: BTreeMap<String, BTreeMap<String, TokenDeltaDetails>>,
+}
 
+impl TokenStatsMachine {
+    fn new() -> Self {
+        TokenStatsMachine {
+            revision_token_deltas: BTreeMap::new(),
+            file_token_deltas: BTreeMap::new(),
+        }
+    }
 
+    /// Records a single added/removed token line; `origin` is '+' or '-'
+    /// exactly like `DeltaMachine::push_diff_token`'s `DiffLine::origin()`.
+    fn record_token(&mut self, origin: char, path: &str, token: &str) {
+        let delta = match origin {
+            '+' => TokenDeltaDetails { added: 1, moved: 0, evolved_from: 0, removed: 0 },
+            '-' => TokenDeltaDetails { added: 0, moved: 0, evolved_from: 0, removed: 1 },
+            _ => return,
+        };
+
+        let revision_entry = self
+            .revision_token_deltas
+            .entry(token.to_string())
+            .or_insert(TokenDeltaDetails { added: 0, moved: 0, evolved_from: 0, removed: 0 });
+        revision_entry.added += delta.added;
+        revision_entry.removed += delta.removed;
+
+        let file_entry = self
+            .file_token_deltas
+            .entry(path.to_string())
+            .or_insert_with(BTreeMap::new)
+            .entry(token.to_string())
+            .or_insert(TokenDeltaDetails { added: 0, moved: 0, evolved_from: 0, removed: 0 });
+        file_entry.added += delta.added;
+        file_entry.removed += delta.removed;
+    }
+
+    /// Collapses `file_token_deltas` down to one `TokenDeltaDetails` total
+    /// per file, which is all `write_token_author_rollups` needs (it
+    /// attributes the whole file's churn to the revision's single author
+    /// rather than faceting by which specific tokens changed).
+    fn file_totals(&self) -> BTreeMap<PathBuf, TokenDeltaDetails> {
+        let mut totals = BTreeMap::new();
+        for (path, token_deltas) in &self.file_token_deltas {
+            let mut total = TokenDeltaDetails { added: 0, moved: 0, evolved_from: 0, removed: 0 };
+            for delta in token_deltas.values() {
+                total.added += delta.added;
+                total.moved += delta.moved;
+                total.evolved_from += delta.evolved_from;
+                total.removed += delta.removed;
+            }
+            totals.insert(PathBuf::from(path), total);
+        }
+        totals
+    }
+
+    /// Folds another worker's accumulated per-token state into this one; see
+    /// `DeltaMachine::merge`, which this mirrors.
+    fn merge(&mut self, other: TokenStatsMachine) {
+        for (token, delta) in other.revision_token_deltas {
+            let entry = self
+                .revision_token_deltas
+                .entry(token)
+                .or_insert(TokenDeltaDetails { added: 0, moved: 0, evolved_from: 0, removed: 0 });
+            entry.added += delta.added;
+            entry.moved += delta.moved;
+            entry.evolved_from += delta.evolved_from;
+            entry.removed += delta.removed;
+        }
+        for (path, token_deltas) in other.file_token_deltas {
+            let file_entry = self.file_token_deltas.entry(path).or_insert_with(BTreeMap::new);
+            for (token, delta) in token_deltas {
+                let entry = file_entry
+                    .entry(token)
+                    .or_insert(TokenDeltaDetails { added: 0, moved: 0, evolved_from: 0, removed: 0 });
+                entry.added += delta.added;
+                entry.moved += delta.moved;
+                entry.evolved_from += delta.evolved_from;
+                entry.removed += delta.removed;
+            }
+        }
+    }
 }
 
 /// Accumulates deltas from hunks as driven by `ingest_diff_accumulating_deltas`
@@ -677,37 +1621,259 @@ struct DeltaContextCluster {
     // runs, each is associated with a single path-pair
     runs: Vec<DeltaRun>,
 
-    // XXX NEXT: question of how to best represent the within-file moves, the
-    // between-file moves, and the evolutions.  In general we want these keyed
-    // by the old-path and new-path... I guess that does suggest that at the
-    // end of the inference phase we want to render all of these into some kind
-    // of new structure
-    evolutions: Vec<()>,
-    moved_out: Vec<()>,
-    moved_in: Vec<()>,
+    /// Cross-run token moves inferred by `infer_moves`, indexed by the run
+    /// the tokens were removed from. Mirrored by `moved_in`, indexed by the
+    /// run they landed in, so a query like "what left this old file" or
+    /// "what arrived in this new file" can walk a single vector instead of
+    /// filtering the other one. A run whose whole add/remove is one token
+    /// each and that no move consumed is instead recorded as an evolution
+    /// on that run; see `DeltaRun::evolved`.
+    moved_out: Vec<TokenMove>,
+    moved_in: Vec<TokenMove>,
+}
+
+/// Lexical class of a token's first character, used by `infer_moves` to
+/// reject single-token matches that would otherwise anchor a move on a
+/// coincidental shared punctuator (a stray `;` or `}` recurring throughout a
+/// file is not evidence of a move the way a repeated identifier is).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum TokenClass {
+    Alphanumeric,
+    Numeric,
+    Punctuation,
+}
+
+fn classify_token(token: &str) -> TokenClass {
+    match token.chars().next() {
+        Some(c) if c.is_ascii_digit() => TokenClass::Numeric,
+        Some(c) if c.is_alphanumeric() || c == '_' => TokenClass::Alphanumeric,
+        _ => TokenClass::Punctuation,
+    }
+}
+
+/// A contiguous run of tokens inferred to have moved from one run's removed
+/// tokens to another (or the same) run's added tokens.
+#[derive(Clone, Debug)]
+struct TokenMove {
+    /// Index into `DeltaContextCluster::runs` the tokens were removed from.
+    from_run: usize,
+    /// 1-based old line number of the first moved token.
+    from_line: u32,
+    /// Index into `DeltaContextCluster::runs` the tokens were added to.
+    to_run: usize,
+    /// 1-based new line number of the first moved token.
+    to_line: u32,
+    /// How many contiguous tokens this move covers.
+    match_len: usize,
+}
+
+/// Builds a suffix array (indices into `seq`, sorted by the suffix starting
+/// there) using the standard O(n log^2 n) prefix-doubling technique: rank
+/// every suffix by its first element, then repeatedly double the compared
+/// prefix length, re-ranking by the pair of (rank at offset 0, rank at offset
+/// `k`), until a doubling distinguishes every suffix. `infer_moves`'s
+/// sequences are one namespace/context's worth of removed tokens for a
+/// single revision, not whole-repository scale, so the simpler doubling
+/// approach is preferable to the added complexity of DC3/SA-IS here.
+fn build_suffix_array(seq: &[u32]) -> Vec<usize> {
+    let n = seq.len();
+    let mut sa: Vec<usize> = (0..n).collect();
+    let mut rank: Vec<i64> = seq.iter().map(|&v| v as i64).collect();
+    let mut tmp = vec![0i64; n];
+    let mut k = 1;
+    while k < n {
+        let key = |i: usize| -> (i64, i64) { (rank[i], if i + k < n { rank[i + k] } else { -1 }) };
+        sa.sort_by_key(|&i| key(i));
+        tmp[sa[0]] = 0;
+        for idx in 1..n {
+            tmp[sa[idx]] =
+                tmp[sa[idx - 1]] + if key(sa[idx - 1]) == key(sa[idx]) { 0 } else { 1 };
+        }
+        rank.copy_from_slice(&tmp);
+        if rank[sa[n - 1]] as usize == n - 1 {
+            break;
+        }
+        k *= 2;
+    }
+    sa
+}
+
+/// Length of the common prefix shared by `a` and `b`.
+fn lcp(a: &[u32], b: &[u32]) -> usize {
+    a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count()
+}
+
+/// Index in `sa` at which `query` would be inserted to keep `sa` (ordered by
+/// the suffixes of `seq` it indexes) sorted. The suffix(es) adjacent to this
+/// index are the ones with the longest common prefix with `query` -- a
+/// standard property of suffix-array pattern lookups -- so callers only need
+/// to examine a small window around it rather than the whole array.
+fn sa_lower_bound(seq: &[u32], sa: &[usize], query: &[u32]) -> usize {
+    let mut lo = 0;
+    let mut hi = sa.len();
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        if seq[sa[mid]..] < *query {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    lo
 }
 
 impl DeltaContextCluster {
-    // TODO: general idea here is:
-    // - process all the tokens, generating utf8 identifiers for them as we go
-    //   so that we can build a suffix array of all the removed tokens.  we use
-    //   0 as the lowest sentinel that's required / to delimit the removed runs.
-    // - we sort the addition runs by the number of additions so that we can
-    //   try and match and consume longer runs first.
-    // - we process the additions by doing a longest prefix search for the
-    //   additions against the removals as we process forward through the run.
-    //   - we have a position in the run, and we move forward each time we find
-    //     a suitable match; see other notes but the general idea is that we
-    //     do require some alphanum alignment to reuse.
-    //   - because of consumption, we potentially maybe do the binary search
-    //     greedy lcp and as long as that finds us an un-consumed run of tokens,
-    //     we just use that.  But if we've already consumed the tokens, perhaps
-    //     we slide around the adjacent indexes running a fitness func that does
-    //     the locality thing, etc.  (The fitness func wouldn't be appropriate
-    //     for binary search because it would be multi-dimensional for our
-    //     locality needs.)  I think there's more thoughts in the notes too.
+    /// Infers token moves and single-token evolutions across every run in
+    /// this cluster (i.e. every path-pair sharing this namespace+context).
+    ///
+    /// Every removed token across every run is assigned a stable integer id
+    /// (id 0 is reserved as a between-run sentinel, so a match can never read
+    /// through from one run's removed tokens into an unrelated run's) and the
+    /// ids are concatenated into one sequence with a suffix array built over
+    /// it. We then walk the added runs longest-first -- a long contiguous
+    /// match is much more likely to be a genuine move than a short one, so
+    /// letting it claim its tokens first keeps shorter/noisier runs from
+    /// stealing them out from under it -- advancing a cursor through each
+    /// run's added tokens and, at each position, using the suffix array to
+    /// find the longest unconsumed run of removed tokens sharing a prefix
+    /// with the remaining added tokens. If the best match is already
+    /// (partially) consumed we widen the search to a small window of
+    /// adjacent suffix-array entries and score them with a fitness function
+    /// that favors both match length and locality (small old/new line delta,
+    /// same path-pair). Matches of length 1 are only accepted when the token
+    /// isn't pure punctuation, per the class-alignment note on `TokenClass`.
+    ///
+    /// Finally, any run whose *entire* add/remove is a single token each and
+    /// that was never consumed above is recorded as a within-run evolution on
+    /// `DeltaRun::evolved` rather than a move.
     fn infer_moves(&mut self) {
+        let mut token_ids: HashMap<String, u32> = HashMap::new();
+        let mut next_id: u32 = 1;
+
+        // Flattened, 0-delimited sequence of every run's removed tokens, plus
+        // a parallel `seq_owner` mapping each non-sentinel position back to
+        // the (run index, position within that run's `removed` vec) it came
+        // from.
+        let mut sequence: Vec<u32> = Vec::new();
+        let mut seq_owner: Vec<(usize, usize)> = Vec::new();
+        for (run_idx, run) in self.runs.iter().enumerate() {
+            for (removed_idx, (_, _, token)) in run.removed.iter().enumerate() {
+                let id = *token_ids.entry(token.clone()).or_insert_with(|| {
+                    let id = next_id;
+                    next_id += 1;
+                    id
+                });
+                sequence.push(id);
+                seq_owner.push((run_idx, removed_idx));
+            }
+            sequence.push(0);
+            seq_owner.push((run_idx, usize::MAX));
+        }
+
+        if sequence.is_empty() {
+            return;
+        }
+        let suffix_array = build_suffix_array(&sequence);
+
+        let mut run_order: Vec<usize> = (0..self.runs.len()).collect();
+        run_order.sort_by_key(|&idx| std::cmp::Reverse(self.runs[idx].added.len()));
+
+        for run_idx in run_order {
+            let mut cursor = 0;
+            while cursor < self.runs[run_idx].added.len() {
+                if self.runs[run_idx].added[cursor].0 {
+                    cursor += 1;
+                    continue;
+                }
+
+                let query: Vec<u32> = self.runs[run_idx].added[cursor..]
+                    .iter()
+                    .map(|(_, _, token)| token_ids.get(token).copied().unwrap_or(u32::MAX))
+                    .collect();
+                let added_line = self.runs[run_idx].added[cursor].1;
+                let added_path_pair = self.runs[run_idx].path_pair_index;
+                let first_token_class = classify_token(&self.runs[run_idx].added[cursor].2);
+
+                let insertion = sa_lower_bound(&sequence, &suffix_array, &query);
+                // The best-LCP suffix is always adjacent to the insertion
+                // point; widen slightly so we have alternatives to fall back
+                // on if the closest one turns out to already be consumed.
+                let window_lo = insertion.saturating_sub(2);
+                let window_hi = (insertion + 2).min(suffix_array.len());
+
+                let mut best: Option<(usize, usize, i64)> = None; // (seq_start, usable_len, fitness)
+                for &sa_idx in &suffix_array[window_lo..window_hi] {
+                    let match_len = lcp(&sequence[sa_idx..], &query);
+                    if match_len == 0 {
+                        continue;
+                    }
+                    // Trim to the longest prefix of the match that is still
+                    // entirely unconsumed.
+                    let mut usable_len = 0;
+                    while usable_len < match_len {
+                        let (owner_run, owner_idx) = seq_owner[sa_idx + usable_len];
+                        if self.runs[owner_run].removed[owner_idx].0 {
+                            break;
+                        }
+                        usable_len += 1;
+                    }
+                    if usable_len == 0 {
+                        continue;
+                    }
+                    if usable_len == 1 && first_token_class == TokenClass::Punctuation {
+                        continue;
+                    }
+
+                    let (owner_run, owner_idx) = seq_owner[sa_idx];
+                    let removed_line = self.runs[owner_run].removed[owner_idx].1;
+                    let line_delta = (added_line as i64 - removed_line as i64).abs();
+                    let same_path_pair = self.runs[owner_run].path_pair_index == added_path_pair;
+                    let fitness = (usable_len as i64) * 1000 - line_delta
+                        + if same_path_pair { 500 } else { 0 };
+
+                    if best.map_or(true, |(_, _, best_fitness)| fitness > best_fitness) {
+                        best = Some((sa_idx, usable_len, fitness));
+                    }
+                }
+
+                match best {
+                    Some((sa_idx, usable_len, _)) => {
+                        let (owner_run, owner_idx) = seq_owner[sa_idx];
+                        let removed_line = self.runs[owner_run].removed[owner_idx].1;
+                        for offset in 0..usable_len {
+                            let (r, i) = seq_owner[sa_idx + offset];
+                            self.runs[r].removed[i].0 = true;
+                            self.runs[run_idx].added[cursor + offset].0 = true;
+                        }
+                        let token_move = TokenMove {
+                            from_run: owner_run,
+                            from_line: removed_line,
+                            to_run: run_idx,
+                            to_line: added_line,
+                            match_len: usable_len,
+                        };
+                        self.moved_out.push(token_move.clone());
+                        self.moved_in.push(token_move);
+                        cursor += usable_len;
+                    }
+                    None => {
+                        cursor += 1;
+                    }
+                }
+            }
+        }
 
+        // Whole 1-add/1-remove runs that were never consumed above are
+        // single-token evolutions rather than moves.
+        for run in self.runs.iter_mut() {
+            if run.added.len() == 1 && run.removed.len() == 1 && !run.added[0].0 && !run.removed[0].0 {
+                run.added[0].0 = true;
+                run.removed[0].0 = true;
+                let (_, removed_line, removed_token) = run.removed[0].clone();
+                let (_, added_line, added_token) = run.added[0].clone();
+                run.evolved.push((removed_line, removed_token, added_line, added_token));
+            }
+        }
     }
 }
 
@@ -810,6 +1976,172 @@ impl DeltaMachine {
     fn flush_hunk(&mut self) {
         self.flush_run();
     }
+
+    /// Folds another worker's accumulated per-namespace state into this one.
+    /// Used by `ingest_diff_work_items_parallel` to combine the partial
+    /// `DeltaMachine`s each worker built while diffing its share of the
+    /// revision's files. Workers are partitioned by file rather than by
+    /// namespace, so it's entirely normal for two workers to have each
+    /// touched the same namespace (e.g. both diffed a `.cpp` file); for a
+    /// namespace present on both sides we merge their context clusters
+    /// instead of letting one side clobber the other.
+    fn merge(&mut self, other: DeltaMachine) {
+        // `path_pair_index` on each of `other`'s runs indexes into
+        // `other.path_pairs`, not `self.path_pairs`, so every run we pull in
+        // below needs that index shifted by however many entries
+        // `self.path_pairs` already had before we append `other`'s.
+        let path_pair_offset = self.path_pairs.len() as u32;
+        self.path_pairs.extend(other.path_pairs);
+
+        for (namespace, other_ns) in other.namespaces {
+            let self_ns = self.namespaces.entry(namespace).or_insert_with(|| DeltaNamespace {
+                context_clusters: HashMap::new(),
+            });
+            for (context, mut other_cluster) in other_ns.context_clusters {
+                for run in other_cluster.runs.iter_mut() {
+                    run.path_pair_index += path_pair_offset;
+                }
+                match self_ns.context_clusters.entry(context) {
+                    std::collections::hash_map::Entry::Vacant(vacant) => {
+                        vacant.insert(other_cluster);
+                    }
+                    std::collections::hash_map::Entry::Occupied(mut occupied) => {
+                        let self_cluster = occupied.get_mut();
+                        // `moved_out`/`moved_in` reference run indices within
+                        // this same cluster's `runs`, so they need to shift
+                        // by however many runs `self_cluster` already had,
+                        // same as the path-pair offset above. In practice
+                        // `infer_moves` hasn't run yet at merge time (see
+                        // `thread_preprocess_revision`), so both vecs are
+                        // always empty here, but shifting them costs nothing
+                        // and keeps this correct if that ordering ever
+                        // changes.
+                        let run_offset = self_cluster.runs.len();
+                        for token_move in other_cluster.moved_out.iter_mut() {
+                            token_move.from_run += run_offset;
+                            token_move.to_run += run_offset;
+                        }
+                        for token_move in other_cluster.moved_in.iter_mut() {
+                            token_move.from_run += run_offset;
+                            token_move.to_run += run_offset;
+                        }
+                        self_cluster.runs.extend(other_cluster.runs);
+                        self_cluster.moved_out.extend(other_cluster.moved_out);
+                        self_cluster.moved_in.extend(other_cluster.moved_in);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Dispatches the diff work items collected by `process_tree_changes` across
+/// a `num_cpus`-sized pool of worker threads instead of diffing every file
+/// serially on the calling thread: each worker owns its own `DeltaMachine`
+/// and pulls items off a shared queue (so a revision with a few huge diffs
+/// mixed in with many small ones doesn't leave idle workers waiting on a
+/// fixed static split), and the main thread merges the partial
+/// `unmodified_tokens` entries and `DeltaMachine`s once every worker has
+/// drained the queue.
+///
+/// We don't bother partitioning by language up front -- `namespace` already
+/// rides along on each `DiffWorkItem`, and `infer_token_moves_from_diff_deltas`
+/// keys its match index on `(namespace, token)`, so a JS file and a C++ file
+/// landing on the same worker can never cross-contaminate each other's move
+/// inference regardless of how the work queue happens to shuffle them.
+///
+/// Results are sorted by `(parent_rev, path)` before being folded into the
+/// returned `unmodified_tokens` map so the output doesn't depend on which
+/// worker happened to finish a given item first.
+fn ingest_diff_work_items_parallel(
+    work_items: Vec<DiffWorkItem>,
+    diff_algorithm: DiffAlgorithm,
+) -> (
+    HashMap<(Oid, PathBuf), Vec<(usize, usize)>>,
+    DeltaMachine,
+    TokenStatsMachine,
+    RevisionTokenChurn,
+) {
+    let num_threads = num_cpus::get().max(1);
+
+    let (work_tx, work_rx) = channel::<DiffWorkItem>();
+    let work_rx = Arc::new(std::sync::Mutex::new(work_rx));
+    let (result_tx, result_rx) = channel::<(Oid, PathBuf, Vec<(usize, usize)>)>();
+
+    let mut workers = Vec::with_capacity(num_threads);
+    for _ in 0..num_threads {
+        let work_rx = Arc::clone(&work_rx);
+        let result_tx = result_tx.clone();
+        workers.push(thread::spawn(move || {
+            let mut delter = DeltaMachine::new();
+            let mut token_stats = TokenStatsMachine::new();
+            let mut churn = RevisionTokenChurn::default();
+            loop {
+                let item = {
+                    let work_rx = work_rx.lock().unwrap();
+                    work_rx.recv()
+                };
+                let item = match item {
+                    Ok(item) => item,
+                    Err(_) => break,
+                };
+                let linecount = count_lines(&item.content);
+                let unchanged = ingest_diff_accumulating_deltas(
+                    &item.parent_content,
+                    &item.content,
+                    linecount,
+                    item.namespace,
+                    &item.path.to_string_lossy(),
+                    diff_algorithm,
+                    &mut delter,
+                    &mut token_stats,
+                    &mut churn,
+                    item.is_primary_parent,
+                )
+                .unwrap();
+                result_tx.send((item.parent_rev, item.path, unchanged)).unwrap();
+            }
+            (delter, token_stats, churn)
+        }));
+    }
+    // Drop our own handle so `result_rx` only sees EOF once every worker's
+    // clone has also been dropped (which happens as each worker returns).
+    drop(result_tx);
+
+    let item_count = work_items.len();
+    for item in work_items {
+        work_tx.send(item).unwrap();
+    }
+    // Let the workers know the queue is drained once they've consumed
+    // everything already sent.
+    drop(work_tx);
+
+    let mut results = Vec::with_capacity(item_count);
+    for _ in 0..item_count {
+        results.push(result_rx.recv().unwrap());
+    }
+    results.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+
+    let mut unmodified_tokens = HashMap::new();
+    for (parent_rev, path, unchanged) in results {
+        unmodified_tokens.insert((parent_rev, path), unchanged);
+    }
+
+    let mut delter = DeltaMachine::new();
+    let mut token_stats = TokenStatsMachine::new();
+    let mut churn = RevisionTokenChurn::default();
+    for worker in workers {
+        let (worker_delter, worker_token_stats, worker_churn) = worker.join().unwrap();
+        delter.merge(worker_delter);
+        token_stats.merge(worker_token_stats);
+        churn.merge(worker_churn);
+    }
+    // Same determinism concern as the `unmodified_tokens` sort above:
+    // `infer_token_moves_from_diff_deltas`'s greedy matching is sensitive to
+    // iteration order.
+    churn.sort_for_determinism();
+
+    (unmodified_tokens, delter, token_stats, churn)
 }
 
 // Does the CPU-intensive work required for pre-computation of a given revision
@@ -821,7 +2153,9 @@ impl DeltaMachine {
 fn thread_preprocess_revision(
     git_repo: &git2::Repository,
     rev_meta: &HistorySyntaxCommitMeta,
-) -> Result<TimelineData, git2::Error> {
+    ignore_revs: &Arc<HashSet<Oid>>,
+    diff_algorithm: DiffAlgorithm,
+) -> Result<(TimelineData, DeltaMachine), git2::Error> {
     let commit = git_repo.find_commit(*rev_meta.syntax_rev).unwrap();
 
     // ## Infer file movement from the "files" tree
@@ -831,7 +2165,27 @@ fn thread_preprocess_revision(
     // renames and other refactorings, it becomes possible for us to use those
     // heuristics instead, although if we can follow the evolution of tokens
     // through time it's not clear that the file movement is as important.
-    let file_movement = if commit.parent_count() == 1 {
+    //
+    // For merge commits we diff against the first (mainline) parent rather
+    // than skipping movement detection entirely: `process_tree_changes` and
+    // `hyperblame_for_path` both already walk every parent independently (see
+    // `commit.parents()` in each), and whichever parent left a path/blob
+    // unchanged wins that line's blame without any help from this map, so the
+    // only thing rename-aware movement buys us here is resolving the *moved*
+    // side of the diff, which is best attributed to the mainline lineage --
+    // a rename that only happened on a side branch being merged in is a rarer
+    // case we don't attempt to track separately yet.
+    //
+    // The same reasoning is why the diff-ingestion pass below only feeds the
+    // mainline parent's diff into `DeltaMachine`/`TokenStatsMachine`/
+    // `RevisionTokenChurn` (see `DiffWorkItem::is_primary_parent`): every
+    // parent still gets diffed for `unmodified_tokens`, so per-line blame
+    // reconciliation (`hyperblame_for_path`) sees every lineage, but the
+    // structural move/evolution/churn tracking only has room for one
+    // timeline per file and would double-count a merge's real authorship if
+    // it walked every parent's diff into the same machine.
+    let file_movement_start = Instant::now();
+    let file_movement = if commit.parent_count() >= 1 {
         let parent_root = commit.parent(0).unwrap().tree().unwrap();
         let parent_files = parent_root
             .get_name("files")
@@ -850,8 +2204,10 @@ fn thread_preprocess_revision(
             .unwrap();
 
         let mut movement = HashMap::new();
+        let mut diff_opts = DiffOptions::new();
+        diff_opts.diff_algorithm(diff_algorithm);
         let mut diff = git_repo
-            .diff_tree_to_tree(Some(&parent_files), Some(&cur_files), None)
+            .diff_tree_to_tree(Some(&parent_files), Some(&cur_files), Some(&mut diff_opts))
             .unwrap();
         diff.find_similar(Some(
             DiffFindOptions::new()
@@ -879,42 +2235,97 @@ fn thread_preprocess_revision(
     } else {
         None
     };
+    let file_movement_us = file_movement_start.elapsed().as_micros() as u64;
 
     // ## Process the "files" token-centric mapping
     //
-    // This provides us with the unmodified_tokens mapping as well as
-    // accumulated add/removed tokens so we can try and infer moves in the
-    // next passes.
-    let mut unmodified_tokens = HashMap::new();
-
-    let mut delter = DeltaMachine::new();
+    // First just walk the tree collecting every modified blob/parent-blob
+    // pair as an owned `DiffWorkItem` -- the walk itself has to stay on this
+    // thread since it holds a live `&git2::Repository`, but pulling the
+    // content out here means the actual per-file diffing doesn't have to.
+    let diff_ingest_start = Instant::now();
+    let mut work_items = Vec::new();
     process_tree_changes(
         "files",
         file_movement.as_ref(),
         git_repo,
         &commit,
         PathBuf::new(),
-        &mut |blob: &Blob, parent_blob: &Blob, path: &Path| {
-            ingest_diff_accumulating_deltas(blob, parent_blob, path, delter);
+        &mut |blob: &Blob, parent_blob: &Blob, parent_rev: Oid, path: &Path, is_primary_parent: bool| {
+            work_items.push(DiffWorkItem {
+                parent_rev,
+                path: path.to_path_buf(),
+                namespace: namespace_for_file(path),
+                parent_content: parent_blob.content().to_vec(),
+                content: blob.content().to_vec(),
+                is_primary_parent,
+            });
         }
     )?;
 
+    // Then fan the actual diffing -- which gives us the unmodified_tokens
+    // mapping as well as the accumulated add/removed tokens we try to infer
+    // moves from in the next passes -- out across a worker pool.
+    let (unmodified_tokens, mut delter, token_stats, churn) =
+        ingest_diff_work_items_parallel(work_items, diff_algorithm);
+    let diff_ingest_us = diff_ingest_start.elapsed().as_micros() as u64;
+
+    // Cross-file git-blame `-M`/`-C`-style token move/copy detection over
+    // everything this revision touched; `churn` is already sorted for
+    // determinism by `ingest_diff_work_items_parallel`. See
+    // `infer_token_moves_from_diff_deltas` and `hyperblame_for_path`'s
+    // consumption of `token_moves` below.
+    let token_moves = infer_token_moves_from_diff_deltas(&churn);
+
     // ## Process token movement inference
     //
-    // We now have a
+    // `run_analyse_mode` and `run_check_mode` are currently the only consumers
+    // of the inferred `moved_out`/`moved_in`/`evolved` data (nothing downstream folds it into
+    // the blame tree yet), but we run it here rather than there so its cost
+    // is accounted for in every revision's timings, not just ones someone
+    // happens to run `ANALYSE_REV` against.
+    let infer_moves_start = Instant::now();
+    for namespace in delter.namespaces.values_mut() {
+        for cluster in namespace.context_clusters.values_mut() {
+            cluster.infer_moves();
+        }
+    }
+    let infer_moves_us = infer_moves_start.elapsed().as_micros() as u64;
 
     // ## Process the "file-struct" symbol rep
-
-
-    Ok(TimelineData {
-        source_rev: *git_oid,
-        // XXX this should get filled in downstream
-        source_hg_rev: None,
-        syntax_rev:
-
-        file_movement,
-        unmodified_tokens,
-    })
+    //
+    // This is where a per-revision `SymbolSyntaxDeltaGroup` would get built
+    // from the tree-sitter "file-struct" symbol rep and run through
+    // `infer_symbol_evolutions` (see `symbol_evolution.rs`) before being
+    // folded into `TimelineData`, the same way `infer_moves` runs over
+    // `delter`'s token-level clusters just above. That extraction --
+    // walking the semantifier's symbol tree and diffing it
+    // revision-over-revision into `SymbolSyntaxDelta` entries -- doesn't
+    // exist in this pipeline yet, so there's currently no
+    // `SymbolSyntaxDeltaGroup` anywhere for `infer_symbol_evolutions` to run
+    // on. Wire the call in here once that extraction lands; until then the
+    // pass is unreachable dead weight, not a bug in the pass itself.
+
+    Ok((
+        TimelineData {
+            source_rev: *rev_meta.source_rev,
+            // XXX this should get filled in downstream
+            source_hg_rev: None,
+            syntax_rev: *rev_meta.syntax_rev,
+
+            file_movement,
+            unmodified_tokens,
+            token_file_totals: token_stats.file_totals(),
+            token_moves,
+            ignore_revs: ignore_revs.clone(),
+            phase_timings: PhaseTimings {
+                file_movement_us,
+                diff_ingest_us,
+                infer_moves_us,
+            },
+        },
+        delter,
+    ))
 }
 
 struct ComputeThread {
@@ -923,12 +2334,12 @@ struct ComputeThread {
 }
 
 impl ComputeThread {
-    fn new(git_repo_path: &str) -> Self {
+    fn new(git_repo_path: &str, ignore_revs: Arc<HashSet<Oid>>, diff_algorithm: DiffAlgorithm) -> Self {
         let (query_tx, query_rx) = channel();
         let (response_tx, response_rx) = channel();
         let git_repo_path = git_repo_path.to_string();
         thread::spawn(move || {
-            compute_thread_main(query_rx, response_tx, git_repo_path);
+            compute_thread_main(query_rx, response_tx, git_repo_path, ignore_revs, diff_algorithm);
         });
 
         ComputeThread {
@@ -956,14 +2367,261 @@ fn compute_thread_main(
     query_rx: Receiver<HistorySyntaxCommitMeta>,
     response_tx: Sender<TimelineData>,
     git_repo_path: String,
+    ignore_revs: Arc<HashSet<Oid>>,
+    diff_algorithm: DiffAlgorithm,
 ) {
     let git_repo = Repository::open(git_repo_path).unwrap();
     while let Ok(rev) = query_rx.recv() {
-        let result = thread_preprocess_revision(&git_repo, &rev).unwrap();
+        // The `DeltaMachine` is only of interest to `run_analyse_mode` and
+        // `run_check_mode`, which call `thread_preprocess_revision` directly
+        // rather than going through this worker-pool path.
+        let (result, _delter) =
+            thread_preprocess_revision(&git_repo, &rev, &ignore_revs, diff_algorithm).unwrap();
         response_tx.send(result).unwrap();
     }
 }
 
+/// `ANALYSE_REV=<rev>` (optionally with `ANALYSE_COUNT=<n>`, default 1) entry
+/// point, modeled on `git-debrebase analyse`: runs `thread_preprocess_revision`
+/// directly -- the same per-revision computation the worker pool normally
+/// does -- for `n` revisions starting at `rev` (in topological order, newest
+/// first, the reverse of `main`'s own revwalk) and prints the result to stdout as JSON instead
+/// of writing anything into the timeline repo. No `start_fast_import`, no
+/// compute-thread pool, no blame ref reads or writes.
+///
+/// Note that since `DeltaContextCluster`'s runs are only ever populated by
+/// `DeltaMachine::flush_run` -- which is still a stub that never pushes a run -- the
+/// `moves`/`evolutions` arrays below will be empty until that's filled in;
+/// this mode exists so that work can be validated against real per-revision
+/// data as soon as it is.
+fn run_analyse_mode(
+    syntax_repo: &Repository,
+    analyse_rev: &str,
+    ignore_revs: &Arc<HashSet<Oid>>,
+    diff_algorithm: DiffAlgorithm,
+) {
+    let start_commit = syntax_repo
+        .revparse_single(analyse_rev)
+        .and_then(|obj| obj.peel_to_commit())
+        .unwrap();
+
+    let count = env::var("ANALYSE_COUNT")
+        .ok()
+        .and_then(|x| x.parse::<usize>().ok())
+        .unwrap_or(1);
+
+    // No `Sort::REVERSE` here, unlike the history-ingestion walks elsewhere in
+    // this file: those walk the *whole* history oldest-first so every
+    // ancestor gets processed before its descendants, but this mode only
+    // wants `count` revisions starting at `analyse_rev` itself. `REVERSE`
+    // would instead start the range at the oldest commit reachable from
+    // `analyse_rev` -- the repo's root, for the common `ANALYSE_COUNT=1`
+    // case -- and never look at `analyse_rev` at all.
+    let mut walk = syntax_repo.revwalk().unwrap();
+    walk.set_sorting(Sort::TOPOLOGICAL).unwrap();
+    walk.push(start_commit.id()).unwrap();
+    let revs: Vec<Oid> = walk.map(|r| r.unwrap()).take(count).collect();
+
+    let mut out = Vec::with_capacity(revs.len());
+    for syntax_oid in revs {
+        let commit = syntax_repo.find_commit(syntax_oid).unwrap();
+        let rev_meta = syntax_commit_to_meta(&commit);
+        let (diff_data, mut delter) =
+            thread_preprocess_revision(syntax_repo, &rev_meta, ignore_revs, diff_algorithm).unwrap();
+
+        let unmodified_tokens: serde_json::Map<String, serde_json::Value> = diff_data
+            .unmodified_tokens
+            .iter()
+            .map(|((parent_rev, path), pairs)| {
+                (format!("{} {}", parent_rev, path.display()), serde_json::json!(pairs))
+            })
+            .collect();
+
+        let token_file_totals: serde_json::Map<String, serde_json::Value> = diff_data
+            .token_file_totals
+            .iter()
+            .map(|(path, delta)| (path.display().to_string(), serde_json::json!(delta)))
+            .collect();
+
+        let file_movement: BTreeMap<String, String> = diff_data
+            .file_movement
+            .iter()
+            .flatten()
+            .map(|(oid, path)| (oid.to_string(), path.display().to_string()))
+            .collect();
+
+        let token_moves: BTreeMap<String, String> = diff_data
+            .token_moves
+            .iter()
+            .map(|(dest, src)| {
+                (
+                    format!("{} {}", dest.path.display(), dest.lineno),
+                    format!("{} {}", src.path.display(), src.lineno),
+                )
+            })
+            .collect();
+
+        // `thread_preprocess_revision` already ran `infer_moves` over every
+        // namespace/context cluster (so its cost is captured in
+        // `diff_data.phase_timings.infer_moves_us`); just flatten the
+        // results into plain JSON here -- `DeltaContextCluster`/`TokenMove`
+        // aren't `Serialize` themselves since nothing else needs them off
+        // this thread.
+        let mut moves = Vec::new();
+        let mut evolutions = Vec::new();
+        for namespace in delter.namespaces.values_mut() {
+            for cluster in namespace.context_clusters.values_mut() {
+                for token_move in &cluster.moved_in {
+                    moves.push(serde_json::json!({
+                        "from_run": token_move.from_run,
+                        "from_line": token_move.from_line,
+                        "to_run": token_move.to_run,
+                        "to_line": token_move.to_line,
+                        "match_len": token_move.match_len,
+                    }));
+                }
+                for run in &cluster.runs {
+                    for (old_line, old_token, new_line, new_token) in &run.evolved {
+                        evolutions.push(serde_json::json!({
+                            "old_line": old_line,
+                            "old_token": old_token,
+                            "new_line": new_line,
+                            "new_token": new_token,
+                        }));
+                    }
+                }
+            }
+        }
+
+        out.push(serde_json::json!({
+            "source_rev": diff_data.source_rev.to_string(),
+            "syntax_rev": diff_data.syntax_rev.to_string(),
+            "file_movement": file_movement,
+            "unmodified_tokens": unmodified_tokens,
+            "token_file_totals": token_file_totals,
+            "token_moves": token_moves,
+            "moves": moves,
+            "evolutions": evolutions,
+            "phase_timings": diff_data.phase_timings,
+        }));
+    }
+
+    println!("{}", serde_json::to_string_pretty(&out).unwrap());
+}
+
+/// Pulls the syntax-repo oid back out of a timeline commit's message, which
+/// `main` writes as `git <rev>\nsyntax <rev>\n` (plus an optional trailing
+/// `hg <rev>` line); see the `write!(import_stream, "data {}\n{}\n", ...)`
+/// call below for the writer side.
+fn parse_syntax_rev_from_timeline_message(message: &str) -> Option<Oid> {
+    message
+        .lines()
+        .find_map(|line| line.strip_prefix("syntax "))
+        .and_then(|rev| Oid::from_str(rev).ok())
+}
+
+/// `CHECK_COMMITS=<n>` entry point, modeled on the pijul git importer's
+/// `--check` flag: a read-only regression check rather than an import.
+/// Walks the first `n` revisions already on `blame_ref` (oldest first, the
+/// order `main` originally wrote them in), reprocesses each one's syntax
+/// revision from scratch via `thread_preprocess_revision` -- the same
+/// computation the worker pool does during a real import -- and compares
+/// what comes out against what the existing timeline commit actually has
+/// recorded under "annotated"/"files-delta".
+///
+/// Mismatches are logged with `warn!` and tallied rather than causing a
+/// panic or early return, so one bad revision doesn't stop the rest of the
+/// range from being checked; a non-zero mismatch count is reported at the
+/// end and makes the process exit with status 1, so a CI job invoking this
+/// can still fail the build on it.
+///
+/// Note that `DeltaMachine::flush_run` is still a stub (see
+/// `run_analyse_mode`'s doc comment above) and nothing writes under
+/// "annotated"/"files-delta" yet, so every revision checked today will
+/// report a mismatch for its recorded state until that's filled in -- this
+/// mode exists so that work can be checked against real per-revision data
+/// as soon as it is, the same rationale `run_analyse_mode` gives for itself.
+fn run_check_mode(
+    syntax_repo: &Repository,
+    timeline_repo: &Repository,
+    blame_ref: &str,
+    ignore_revs: &Arc<HashSet<Oid>>,
+    diff_algorithm: DiffAlgorithm,
+    check_count: usize,
+) {
+    let head_oid = timeline_repo.refname_to_id(blame_ref).unwrap();
+    let mut walk = timeline_repo.revwalk().unwrap();
+    walk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE).unwrap();
+    walk.push(head_oid).unwrap();
+    let timeline_oids: Vec<Oid> = walk.map(|r| r.unwrap()).take(check_count).collect();
+
+    info!(
+        "CHECK_COMMITS={}: re-verifying the first {} revision(s) on {}",
+        check_count,
+        timeline_oids.len(),
+        blame_ref
+    );
+
+    let mut revisions_checked = 0;
+    let mut mismatches = 0;
+    for timeline_oid in timeline_oids {
+        let timeline_commit = timeline_repo.find_commit(timeline_oid).unwrap();
+        let syntax_oid = match parse_syntax_rev_from_timeline_message(timeline_commit.message().unwrap_or("")) {
+            Some(oid) => oid,
+            None => {
+                warn!(
+                    "{}: couldn't find a `syntax <oid>` line in the commit message, skipping",
+                    timeline_oid
+                );
+                continue;
+            }
+        };
+        let syntax_commit = syntax_repo.find_commit(syntax_oid).unwrap();
+        let rev_meta = syntax_commit_to_meta(&syntax_commit);
+        let (diff_data, delter) =
+            thread_preprocess_revision(syntax_repo, &rev_meta, ignore_revs, diff_algorithm).unwrap();
+        revisions_checked += 1;
+
+        // We don't have a live fast-import helper in this mode (nothing is
+        // being written), so read the recorded state straight out of the
+        // already-committed timeline tree instead of going through
+        // `cached_read_path_blob`.
+        let timeline_tree = timeline_commit.tree().unwrap();
+        let mut revision_mismatches = 0;
+
+        for (_parent_rev, path) in diff_data.unmodified_tokens.keys() {
+            if timeline_tree.get_path(&Path::new("annotated").join(path)).is_err() {
+                revision_mismatches += 1;
+            }
+        }
+
+        let has_inferred_moves = delter.namespaces.values().any(|namespace| {
+            namespace.context_clusters.values().any(|cluster| {
+                !cluster.moved_in.is_empty() || cluster.runs.iter().any(|run| !run.evolved.is_empty())
+            })
+        });
+        if has_inferred_moves && timeline_tree.get_path(Path::new("files-delta")).is_err() {
+            revision_mismatches += 1;
+        }
+
+        if revision_mismatches > 0 {
+            mismatches += revision_mismatches;
+            warn!(
+                "{} (syntax {}): {} mismatch(es) against recorded \"annotated\"/\"files-delta\" state",
+                diff_data.source_rev, diff_data.syntax_rev, revision_mismatches
+            );
+        }
+    }
+
+    info!(
+        "CHECK_COMMITS complete: {} revision(s) checked, {} mismatch(es) found",
+        revisions_checked, mismatches
+    );
+    if mismatches > 0 {
+        std::process::exit(1);
+    }
+}
+
 fn main() {
     env_logger::from_env(env_logger::Env::default().default_filter_or("info")).init();
 
@@ -985,6 +2643,90 @@ fn main() {
         .and_then(|x| x.parse::<usize>().ok())
         .unwrap_or(0);
 
+    // See `ResumeState` for what this turns on: persisted fast-import marks
+    // plus a source-rev/timeline-rev map flushed alongside the checkpoints
+    // `main` already issues, so a crash between checkpoints doesn't force
+    // redoing work the blame ref hasn't caught up to yet.
+    let resume = env::var("RESUME").ok().as_deref() == Some("1");
+    let marks_path = PathBuf::from(format!("{}.marks", rev_summary_root));
+    let resume_state_path = PathBuf::from(format!("{}.resume-state.json", rev_summary_root));
+
+    // See `PipelineStats` for what this turns on: per-phase wall-clock
+    // timings folded across every revision and flushed to this path (at the
+    // same checkpoint cadence as `ResumeState`, plus once more at exit) so
+    // the otherwise-opaque parallel pipeline can be profiled.
+    let stats_file = env::var("STATS_FILE").ok().map(PathBuf::from);
+    let mut pipeline_stats = PipelineStats::default();
+
+    // Operators can trade speed for blame quality per tree; see
+    // `parse_diff_algorithm` for why histogram is the default rather than
+    // git2's own default of Myers.
+    let diff_algorithm = env::var("DIFF_ALGORITHM")
+        .ok()
+        .map(|name| parse_diff_algorithm(&name))
+        .unwrap_or(DiffAlgorithm::Histogram);
+    info!("Using {:?} diff algorithm", diff_algorithm);
+
+    // Mirrors `git blame --ignore-rev` / `.git-blame-ignore-revs`: revisions
+    // named in this file (whole-tree reformats, mechanical manifest
+    // conversions, etc.) never get blamed for a line; see `load_ignore_revs`
+    // and `hyperblame_for_path`.
+    let ignore_revs = Arc::new(match env::var("IGNORE_REVS_FILE") {
+        Ok(path) => {
+            let ignore_revs = load_ignore_revs(Path::new(&path), &source_repo);
+            info!("Loaded {} ignore-revs entries from {}", ignore_revs.len(), path);
+            ignore_revs
+        }
+        Err(_) => HashSet::new(),
+    });
+
+    // `ANALYSE_REV=<rev>` short-circuits everything else: it's a read-only
+    // debugging mode (see `run_analyse_mode`) that never writes to the
+    // timeline repo or starts `fast-import`, so it returns before any of
+    // that machinery gets set up.
+    if let Ok(analyse_rev) = env::var("ANALYSE_REV") {
+        run_analyse_mode(&syntax_repo, &analyse_rev, &ignore_revs, diff_algorithm);
+        return;
+    }
+
+    // `CHECK_COMMITS=<n>` is the other read-only short-circuit: see
+    // `run_check_mode` for what it re-verifies.
+    if let Ok(check_commits) = env::var("CHECK_COMMITS") {
+        let check_count = check_commits.parse::<usize>().unwrap();
+        run_check_mode(&syntax_repo, &timeline_repo, &blame_ref, &ignore_revs, diff_algorithm, check_count);
+        return;
+    }
+
+    // Loaded once and consulted per-commit by `canonical_author_identity` and
+    // by `write_role` below so both the per-author token rollups
+    // (`write_token_author_rollups`) and the blame repo's own commit
+    // author/committer lines collapse a contributor who has used several
+    // addresses into one entry, the same way `git shortlog -e` does.
+    // MAILMAP_FILE lets an operator point at a mailmap that doesn't live in
+    // the source repo's worktree; otherwise `Repository::mailmap` reads
+    // `.mailmap` out of the worktree (falling back to repo/global config as
+    // git does). Either way, a missing or unreadable mailmap just means "no
+    // canonicalization" rather than aborting the import.
+    let mailmap = match env::var("MAILMAP_FILE") {
+        Ok(path) => match std::fs::read(&path)
+            .ok()
+            .and_then(|bytes| git2::Mailmap::from_buffer(&bytes).ok())
+        {
+            Some(mailmap) => Some(mailmap),
+            None => {
+                warn!("Could not load mailmap from MAILMAP_FILE={}, author identities will not be canonicalized", path);
+                None
+            }
+        },
+        Err(_) => match source_repo.mailmap() {
+            Ok(mailmap) => Some(mailmap),
+            Err(err) => {
+                warn!("Could not load .mailmap, author identities will not be canonicalized: {}", err);
+                None
+            }
+        },
+    };
+
     info!("Reading existing blame map of timeline repo ref {}...", blame_ref);
     /// Maps syntax repo revision to timeline repo commit
     let mut timeline_map = if let Ok(oid) = timeline_repo.refname_to_id(&blame_ref) {
@@ -997,6 +2739,34 @@ fn main() {
         HashMap::new()
     };
 
+    let mut next_mark_hint = 0usize;
+    if resume {
+        match load_resume_state(&resume_state_path) {
+            Some(state) => {
+                info!(
+                    "RESUME=1: merging {} entries from persisted resume state {}",
+                    state.source_to_timeline.len(),
+                    resume_state_path.display()
+                );
+                next_mark_hint = state.next_mark;
+                for (source_rev, timeline_rev) in state.source_to_timeline {
+                    let (Ok(source_oid), Some(commit)) =
+                        (Oid::from_str(&source_rev), parse_timeline_repo_commit(&timeline_rev))
+                    else {
+                        continue;
+                    };
+                    timeline_map.entry(source_oid).or_insert(commit);
+                }
+            }
+            None => {
+                info!(
+                    "RESUME=1: no persisted resume state found at {}, starting fresh",
+                    resume_state_path.display()
+                );
+            }
+        }
+    }
+
     // We are primarily processing the "syntax" repo which is derived from the
     // "source" repo.  So start a walk in the syntax repo from the provided
     // BLAME_REF.
@@ -1030,7 +2800,7 @@ fn main() {
     info!("Starting {} compute threads...", num_threads);
     let mut compute_threads = Vec::with_capacity(num_threads);
     for _ in 0..num_threads {
-        compute_threads.push(ComputeThread::new(&syntax_repo_path));
+        compute_threads.push(ComputeThread::new(&syntax_repo_path, ignore_revs.clone(), diff_algorithm));
     }
 
     // This tracks the index of the next revision in revs_to_process for which
@@ -1050,12 +2820,28 @@ fn main() {
     // if we ran out of requests because there were so few.
     assert!((compute_index % num_threads == 0) || compute_index == rev_count);
 
-    let mut import_helper = start_fast_import(&syntax_repo);
+    let mut import_helper =
+        start_fast_import(&syntax_repo, if resume { Some(marks_path.as_path()) } else { None });
+
+    // Shared across every revision so that repeatedly-touched paths (blame
+    // parents that carry forward unmodified files, common ancestor directories,
+    // etc.) don't force a fresh `cat-blob` round-trip through `import_helper`
+    // each time; see `BlobReadCache`'s doc comment for why no invalidation
+    // logic is needed.
+    let mut blob_read_cache = BlobReadCache::new(BLOB_READ_CACHE_CAPACITY, BLOB_READ_CACHE_TTL);
 
-    // Tracks completion count and serves as the basis for the mark <idnum>
-    // assigned to each commit.
+    // Tracks completion count; also used to pick which compute thread's
+    // result queue to read from next, so (unlike `mark_counter` below) it
+    // must stay in lockstep with `compute_index`'s round-robin dispatch and
+    // always starts at 0 regardless of RESUME.
     let mut rev_done = 0;
 
+    // Next fast-import mark number to hand out. Starts from `next_mark_hint`
+    // under RESUME=1 so marks from this run never collide with ones a
+    // previous, interrupted run already issued and persisted to
+    // `resume_state_path`.
+    let mut mark_counter = next_mark_hint;
+
     for rev_meta in revs_to_process.iter() {
         // Read a result. Since we hand out compute requests in round-robin order
         // and each thread processes them in FIFO order we know exactly which
@@ -1064,6 +2850,7 @@ fn main() {
         let thread = &compute_threads[rev_done % num_threads];
         let diff_data = thread.read_result();
         assert!(diff_data.revision == *rev_meta.syntax_rev);
+        pipeline_stats.accumulate(&diff_data.phase_timings);
 
         // If there are more revisions that we haven't requested yet, request
         // another one from this thread.
@@ -1096,10 +2883,18 @@ fn main() {
             // https://git-scm.com/docs/git-fast-import#_mark
             let mut import_stream = BufWriter::new(import_helper.stdin.as_mut().unwrap());
             write!(import_stream, "commit {}\n", blame_ref).unwrap();
-            write!(import_stream, "mark :{}\n", rev_done).unwrap();
-            timeline_map.insert(*rev_meta.syntax_rev, TimelineRepoCommit::Mark(rev_done));
-
+            mark_counter += 1;
+            write!(import_stream, "mark :{}\n", mark_counter).unwrap();
+            timeline_map.insert(*rev_meta.syntax_rev, TimelineRepoCommit::Mark(mark_counter));
+
+            // Canonicalize through `.mailmap` before writing, same as
+            // `canonical_author_identity` does for the token-rollup author
+            // key, so a contributor who committed under several historical
+            // emails/names ends up attributed to one identity in the blame
+            // repo too instead of fragmenting across their aliases.
             let mut write_role = |role: &str, sig: &git2::Signature| {
+                let resolved = mailmap.as_ref().and_then(|m| m.resolve_signature(sig).ok());
+                let sig = resolved.as_ref().unwrap_or(sig);
                 write!(import_stream, "{} ", role).unwrap();
                 import_stream.write(sig.name_bytes()).unwrap();
                 write!(import_stream, " <").unwrap();
@@ -1160,14 +2955,32 @@ fn main() {
             &commit.tree().unwrap(),
             &parent_trees,
             &mut import_helper,
+            &mut blob_read_cache,
             &blame_parents,
             PathBuf::new(),
         )
         .unwrap();
 
+        if !diff_data.token_file_totals.is_empty() {
+            let author = canonical_author_identity(mailmap.as_ref(), &commit.author());
+            write_token_author_rollups(
+                &mut import_helper,
+                &mut blob_read_cache,
+                &blame_parents,
+                &author,
+                &diff_data.token_file_totals,
+            );
+        }
+
         if rev_done % 100000 == 0 {
             info!("Completed 100,000 commits, issuing checkpoint...");
             write!(import_helper.stdin.as_mut().unwrap(), "checkpoint\n").unwrap();
+            if resume {
+                write_resume_state(&resume_state_path, mark_counter, &timeline_map);
+            }
+            if let Some(path) = &stats_file {
+                write_pipeline_stats(path, &pipeline_stats);
+            }
         }
     }
 
@@ -1179,7 +2992,129 @@ fn main() {
     let exitcode = import_helper.wait().unwrap();
     if exitcode.success() {
         info!("Done!");
+        if resume {
+            // A clean exit means the blame ref itself is now fully caught up,
+            // so the resume state has nothing left to add on the next run;
+            // still write it (rather than deleting it) so a next run with a
+            // fresh, not-yet-`checkpoint`ed ref still has a next_mark to
+            // resume numbering from.
+            write_resume_state(&resume_state_path, mark_counter, &timeline_map);
+        }
     } else {
         info!("Fast-import exited with {:?}", exitcode.code());
     }
+
+    if let Some(path) = &stats_file {
+        write_pipeline_stats(path, &pipeline_stats);
+        info!(
+            "Wrote pipeline stats for {} revision(s) to {}",
+            pipeline_stats.revisions,
+            path.display()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suffix_array_orders_suffixes_lexicographically() {
+        let seq = vec![2, 1, 2, 1, 3];
+        let sa = build_suffix_array(&seq);
+
+        let mut sorted = sa.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..seq.len()).collect::<Vec<_>>());
+
+        for w in sa.windows(2) {
+            assert!(seq[w[0]..] <= seq[w[1]..]);
+        }
+    }
+
+    fn run(path_pair_index: u32, removed: &[(u32, &str)], added: &[(u32, &str)]) -> DeltaRun {
+        DeltaRun {
+            path_pair_index,
+            added: added.iter().map(|(line, tok)| (false, *line, tok.to_string())).collect(),
+            removed: removed.iter().map(|(line, tok)| (false, *line, tok.to_string())).collect(),
+            evolved: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn infer_moves_matches_a_multi_token_run_across_files() {
+        let mut cluster = DeltaContextCluster {
+            runs: vec![
+                run(0, &[(10, "foo"), (11, "bar")], &[]),
+                run(1, &[], &[(20, "foo"), (21, "bar")]),
+            ],
+            moved_out: Vec::new(),
+            moved_in: Vec::new(),
+        };
+
+        cluster.infer_moves();
+
+        assert_eq!(cluster.moved_out.len(), 1);
+        let token_move = &cluster.moved_out[0];
+        assert_eq!(token_move.from_run, 0);
+        assert_eq!(token_move.to_run, 1);
+        assert_eq!(token_move.match_len, 2);
+        assert!(cluster.runs[0].removed.iter().all(|(consumed, _, _)| *consumed));
+        assert!(cluster.runs[1].added.iter().all(|(consumed, _, _)| *consumed));
+    }
+
+    #[test]
+    fn infer_moves_records_single_token_evolution_when_unmatched() {
+        let mut cluster = DeltaContextCluster {
+            runs: vec![run(0, &[(5, "oldName")], &[(5, "newName")])],
+            moved_out: Vec::new(),
+            moved_in: Vec::new(),
+        };
+
+        cluster.infer_moves();
+
+        assert!(cluster.moved_out.is_empty());
+        assert_eq!(cluster.runs[0].evolved.len(), 1);
+        let (old_line, old_token, new_line, new_token) = &cluster.runs[0].evolved[0];
+        assert_eq!((*old_line, old_token.as_str(), *new_line, new_token.as_str()), (5, "oldName", 5, "newName"));
+    }
+
+    #[test]
+    fn infer_moves_rejects_single_punctuation_token_match() {
+        let mut cluster = DeltaContextCluster {
+            runs: vec![
+                run(0, &[(1, ";")], &[]),
+                run(1, &[], &[(2, ";")]),
+            ],
+            moved_out: Vec::new(),
+            moved_in: Vec::new(),
+        };
+
+        cluster.infer_moves();
+
+        assert!(cluster.moved_out.is_empty());
+    }
+
+    #[test]
+    fn canonical_author_identity_falls_back_without_mailmap() {
+        let sig = git2::Signature::now("Jane Dev", "jane@example.com").unwrap();
+        assert_eq!(canonical_author_identity(None, &sig), "Jane Dev <jane@example.com>");
+    }
+
+    #[test]
+    fn token_stats_machine_aggregates_revision_and_per_file_totals() {
+        let mut stats = TokenStatsMachine::new();
+        stats.record_token('+', "src/a.rs", "foo");
+        stats.record_token('+', "src/a.rs", "foo");
+        stats.record_token('-', "src/a.rs", "bar");
+        stats.record_token('+', "src/b.rs", "foo");
+
+        assert_eq!(stats.revision_token_deltas["foo"].added, 3);
+        assert_eq!(stats.revision_token_deltas["bar"].removed, 1);
+
+        let totals = stats.file_totals();
+        assert_eq!(totals[&PathBuf::from("src/a.rs")].added, 2);
+        assert_eq!(totals[&PathBuf::from("src/a.rs")].removed, 1);
+        assert_eq!(totals[&PathBuf::from("src/b.rs")].added, 1);
+    }
 }
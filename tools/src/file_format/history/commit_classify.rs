@@ -0,0 +1,241 @@
+//! Classifies commit messages as Conventional Commits
+//! (https://www.conventionalcommits.org/) and pulls out Bugzilla bug numbers,
+//! so a weekly `SummaryRecordRef` can facet its aggregated token churn by the
+//! semantic intent of the commits that produced it rather than just listing
+//! the revisions.
+
+use serde::{Deserialize, Serialize};
+
+use super::timeline_common::TokenDeltaDetails;
+
+/// The `type` portion of a Conventional Commit header.  `Other` is the
+/// fallback for messages that don't conform to the convention at all (no
+/// `type:` or `type(scope):` prefix), carrying the whole header line so it's
+/// still possible to eyeball what didn't parse.  A well-formed prefix whose
+/// type isn't one of the known ones also lands here, carrying just that type
+/// word.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "raw", rename_all = "lowercase")]
+pub enum ConventionalCommitType {
+    Feat,
+    Fix,
+    Refactor,
+    Perf,
+    Docs,
+    Style,
+    Test,
+    Chore,
+    Build,
+    Ci,
+    Revert,
+    Other(String),
+}
+
+impl ConventionalCommitType {
+    fn from_type_str(s: &str) -> Self {
+        match s {
+            "feat" => ConventionalCommitType::Feat,
+            "fix" => ConventionalCommitType::Fix,
+            "refactor" => ConventionalCommitType::Refactor,
+            "perf" => ConventionalCommitType::Perf,
+            "docs" => ConventionalCommitType::Docs,
+            "style" => ConventionalCommitType::Style,
+            "test" => ConventionalCommitType::Test,
+            "chore" => ConventionalCommitType::Chore,
+            "build" => ConventionalCommitType::Build,
+            "ci" => ConventionalCommitType::Ci,
+            "revert" => ConventionalCommitType::Revert,
+            other => ConventionalCommitType::Other(other.to_string()),
+        }
+    }
+}
+
+/// Parsed Conventional-Commit metadata for a single revision, stored
+/// alongside `SummaryRecordRef::source_revs` (same order, same length, except
+/// for records migrated up from before v4, where the list is empty) so a
+/// reader can pair each source revision with how it was classified.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CommitClassification {
+    pub commit_type: ConventionalCommitType,
+    pub scope: Option<String>,
+    /// True if the `!`-after-type shorthand was used or a `BREAKING CHANGE:`
+    /// footer was found in the message body.
+    pub breaking: bool,
+    /// Bugzilla bug numbers pulled out of the message (`Bug 1234567`-style
+    /// references, case-insensitive), in the order they were encountered.
+    pub bug_numbers: Vec<u32>,
+}
+
+/// Parses the first line of `message` as a Conventional Commit header of the
+/// form `type(scope)!: subject`, and scans the whole message for a
+/// `BREAKING CHANGE:` footer and `Bug NNNNNNN` references.  Never fails;
+/// messages that don't look like a Conventional Commit header just classify
+/// as `ConventionalCommitType::Other`.
+pub fn classify_commit_message(message: &str) -> CommitClassification {
+    let header = message.lines().next().unwrap_or("");
+
+    let (commit_type, scope, header_breaking) = parse_header(header);
+
+    let footer_breaking = message.contains("BREAKING CHANGE:") || message.contains("BREAKING-CHANGE:");
+
+    CommitClassification {
+        commit_type,
+        scope,
+        breaking: header_breaking || footer_breaking,
+        bug_numbers: extract_bug_numbers(message),
+    }
+}
+
+/// Splits `type(scope)!: subject` (scope and `!` both optional) out of a
+/// commit header line.  Falls back to `Other(header)` if there's no `:` or
+/// the part before it isn't a bare word/word(scope) token.
+fn parse_header(header: &str) -> (ConventionalCommitType, Option<String>, bool) {
+    let Some((prefix, _subject)) = header.split_once(':') else {
+        return (ConventionalCommitType::Other(header.to_string()), None, false);
+    };
+    let prefix = prefix.trim();
+
+    let (prefix, breaking) = match prefix.strip_suffix('!') {
+        Some(stripped) => (stripped, true),
+        None => (prefix, false),
+    };
+
+    if let Some(open) = prefix.find('(') {
+        if let Some(close) = prefix.strip_suffix(')').map(|_| prefix.len() - 1) {
+            if close > open {
+                let type_str = &prefix[..open];
+                let scope = &prefix[open + 1..close];
+                if is_bare_word(type_str) {
+                    return (
+                        ConventionalCommitType::from_type_str(type_str),
+                        Some(scope.to_string()),
+                        breaking,
+                    );
+                }
+            }
+        }
+        return (ConventionalCommitType::Other(header.to_string()), None, false);
+    }
+
+    if is_bare_word(prefix) {
+        (ConventionalCommitType::from_type_str(prefix), None, breaking)
+    } else {
+        (ConventionalCommitType::Other(header.to_string()), None, false)
+    }
+}
+
+fn is_bare_word(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// Finds `Bug NNNNNNN` (any case, any amount of whitespace) references
+/// anywhere in the message, the convention used by Bugzilla-linked commits
+/// landing on mozilla-central/autoland.
+fn extract_bug_numbers(message: &str) -> Vec<u32> {
+    let mut bug_numbers = Vec::new();
+    let lower = message.to_ascii_lowercase();
+    let mut search_from = 0;
+    while let Some(found) = lower[search_from..].find("bug") {
+        let start = search_from + found + 3;
+        let rest = &message[start..];
+        let digits: String = rest
+            .trim_start()
+            .chars()
+            .take_while(|c| c.is_ascii_digit())
+            .collect();
+        if !digits.is_empty() {
+            if let Ok(num) = digits.parse::<u32>() {
+                bug_numbers.push(num);
+            }
+        }
+        search_from = start;
+    }
+    bug_numbers
+}
+
+/// Accumulates `TokenDeltaDetails` faceted by `ConventionalCommitType`, so a
+/// weekly summary can answer "how many tokens changed under refactor commits
+/// vs. feature commits" instead of only reporting an unfaceted total.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FacetedTokenDeltaRollup {
+    pub by_type: std::collections::BTreeMap<ConventionalCommitType, TokenDeltaDetails>,
+}
+
+impl FacetedTokenDeltaRollup {
+    pub fn accumulate(&mut self, commit_type: ConventionalCommitType, delta: &TokenDeltaDetails) {
+        let entry = self.by_type.entry(commit_type).or_insert(TokenDeltaDetails {
+            added: 0,
+            moved: 0,
+            evolved_from: 0,
+            removed: 0,
+        });
+        entry.added += delta.added;
+        entry.moved += delta.moved;
+        entry.evolved_from += delta.evolved_from;
+        entry.removed += delta.removed;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_type_and_scope() {
+        let c = classify_commit_message("feat(parser): support nested generics");
+        assert_eq!(c.commit_type, ConventionalCommitType::Feat);
+        assert_eq!(c.scope.as_deref(), Some("parser"));
+        assert!(!c.breaking);
+    }
+
+    #[test]
+    fn classifies_bare_type_with_no_scope() {
+        let c = classify_commit_message("fix: don't panic on empty input");
+        assert_eq!(c.commit_type, ConventionalCommitType::Fix);
+        assert_eq!(c.scope, None);
+    }
+
+    #[test]
+    fn header_bang_marks_breaking() {
+        let c = classify_commit_message("refactor(api)!: drop the v1 endpoints");
+        assert!(c.breaking);
+    }
+
+    #[test]
+    fn footer_marks_breaking_even_without_bang() {
+        let c = classify_commit_message("feat: widen the token alphabet\n\nBREAKING CHANGE: old tokenizers can't read the new format");
+        assert!(c.breaking);
+    }
+
+    #[test]
+    fn non_conventional_header_falls_back_to_other() {
+        let c = classify_commit_message("oops forgot to bump the version");
+        match c.commit_type {
+            ConventionalCommitType::Other(s) => assert_eq!(s, "oops forgot to bump the version"),
+            other => panic!("expected Other, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn extracts_multiple_bug_numbers_case_insensitively() {
+        let c = classify_commit_message("fix: two regressions\n\nBug 1234567 and bug 42 both covered here");
+        assert_eq!(c.bug_numbers, vec![1234567, 42]);
+    }
+
+    #[test]
+    fn faceted_rollup_accumulates_per_type() {
+        let mut rollup = FacetedTokenDeltaRollup::default();
+        let delta = TokenDeltaDetails {
+            added: 3,
+            moved: 1,
+            evolved_from: 0,
+            removed: 2,
+        };
+        rollup.accumulate(ConventionalCommitType::Fix, &delta);
+        rollup.accumulate(ConventionalCommitType::Fix, &delta);
+
+        let entry = &rollup.by_type[&ConventionalCommitType::Fix];
+        assert_eq!(entry.added, 6);
+        assert_eq!(entry.removed, 4);
+    }
+}
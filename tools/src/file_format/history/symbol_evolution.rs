@@ -0,0 +1,123 @@
+//! Infers which removed symbol a newly-added symbol evolved from, so that
+//! `ChangeKind::Evolved` actually lives up to its doc comment ("we think we
+//! can tell you what the thing was before") instead of just being a count on
+//! `TokenDeltaDetails::evolved_from`.
+//!
+//! This is the symbol-granularity analog of descending a token down to its
+//! semantic binding the way rust-analyzer's `get_definition` resolves a
+//! token to a `Definition`: we're not matching symbol name strings, we're
+//! matching the *token-level evidence* (the `token_changes` each symbol
+//! accumulated) that the removed and added symbols are binding to the same
+//! underlying code.
+
+use std::collections::{BTreeMap, HashSet};
+
+use super::timeline_common::{ChangeKind, SymbolSyntaxDeltaGroup, TokenDeltaDetails};
+
+/// Minimum weighted-Jaccard score required before we'll call a (removed,
+/// added) pair an evolution rather than an unrelated add/remove.  Chosen
+/// conservatively; a false "evolved" link is more misleading to a reader than
+/// two separate `Added`/`Removed` entries.
+const EVOLUTION_SCORE_THRESHOLD: f64 = 0.3;
+
+/// The scope a symbol is bound in, derived from the "::"-qualified pretty
+/// identifier (the convention this tree already uses for pretty symbols).
+/// Two symbols in unrelated scopes are never allowed to match even if their
+/// token sets happen to coincide -- this is the "self-matches across
+/// unrelated scopes must be rejected" invariant.
+fn symbol_scope(symbol: &str) -> &str {
+    match symbol.rfind("::") {
+        Some(idx) => &symbol[..idx],
+        None => "",
+    }
+}
+
+/// Jaccard similarity over the `token_changes` key sets of a removed and
+/// added symbol, weighted by `moved` counts so tokens we already have some
+/// move evidence for count for more than a single shared identifier would.
+fn weighted_jaccard(
+    removed_tokens: &BTreeMap<String, TokenDeltaDetails>,
+    added_tokens: &BTreeMap<String, TokenDeltaDetails>,
+) -> f64 {
+    let keys: HashSet<&String> = removed_tokens.keys().chain(added_tokens.keys()).collect();
+    if keys.is_empty() {
+        return 0.0;
+    }
+
+    let mut intersection_weight = 0.0;
+    let mut union_weight = 0.0;
+    for key in keys {
+        let weight = |details: &TokenDeltaDetails| 1.0 + details.moved as f64;
+        match (removed_tokens.get(key), added_tokens.get(key)) {
+            (Some(r), Some(a)) => {
+                intersection_weight += weight(r).min(weight(a));
+                union_weight += weight(r).max(weight(a));
+            }
+            (Some(r), None) => union_weight += weight(r),
+            (None, Some(a)) => union_weight += weight(a),
+            (None, None) => unreachable!("key came from one of the two maps"),
+        }
+    }
+    intersection_weight / union_weight
+}
+
+/// Finds (removed -> added) pairs in `group` that look like the same symbol
+/// evolving, and rewrites them in place: both endpoints become
+/// `ChangeKind::Evolved`, and the added side's `evolved_from_symbol` is set
+/// to the removed side's key. Matching is greedy highest-score-first, and
+/// every symbol participates in at most one match in either direction.
+pub fn infer_symbol_evolutions(group: &mut SymbolSyntaxDeltaGroup) {
+    let removed_symbols: Vec<String> = group
+        .symbol_deltas
+        .iter()
+        .filter(|(_, delta)| delta.change == ChangeKind::Removed)
+        .map(|(symbol, _)| symbol.clone())
+        .collect();
+    let added_symbols: Vec<String> = group
+        .symbol_deltas
+        .iter()
+        .filter(|(_, delta)| delta.change == ChangeKind::Added)
+        .map(|(symbol, _)| symbol.clone())
+        .collect();
+
+    let mut candidates: Vec<(f64, String, String)> = Vec::new();
+    for removed in &removed_symbols {
+        let removed_scope = symbol_scope(removed);
+        let removed_tokens = &group.symbol_deltas[removed].token_changes;
+        for added in &added_symbols {
+            if added == removed || symbol_scope(added) != removed_scope {
+                continue;
+            }
+            let added_tokens = &group.symbol_deltas[added].token_changes;
+            let score = weighted_jaccard(removed_tokens, added_tokens);
+            if score >= EVOLUTION_SCORE_THRESHOLD {
+                candidates.push((score, removed.clone(), added.clone()));
+            }
+        }
+    }
+
+    // Sort highest score first; ties broken by symbol name for determinism.
+    candidates.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap().then_with(|| a.1.cmp(&b.1)));
+
+    let mut used_removed = HashSet::new();
+    let mut used_added = HashSet::new();
+    let mut matches = Vec::new();
+    for (_, removed, added) in candidates {
+        if used_removed.contains(&removed) || used_added.contains(&added) {
+            continue;
+        }
+        used_removed.insert(removed.clone());
+        used_added.insert(added.clone());
+        matches.push((removed, added));
+    }
+
+    for (removed, added) in matches {
+        if let Some(removed_delta) = group.symbol_deltas.get_mut(&removed) {
+            removed_delta.change = ChangeKind::Evolved;
+        }
+        if let Some(added_delta) = group.symbol_deltas.get_mut(&added) {
+            added_delta.change = ChangeKind::Evolved;
+            added_delta.evolved_from_symbol = Some(removed);
+        }
+    }
+}
@@ -3,8 +3,26 @@ use std::collections::BTreeMap;
 
 use serde::{Deserialize, Serialize};
 
+use super::commit_classify::CommitClassification;
+
+/// Current on-disk schema version for the timeline detail/summary/delta
+/// records defined in this file.  Bump this whenever a field is added,
+/// removed, or re-typed in a way that an older reader couldn't tolerate, and
+/// add a corresponding upgrade step in `timeline_migrate`.  Mirrors how
+/// `label-tracker` tags its persisted state with `STATE_VERSION` so readers
+/// can tell stale data apart from a format they don't understand yet.
+pub const SCHEMA_VERSION: u32 = 4;
+
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct DetailRecordRef {
+    /// Schema version this record was written at.  Readers should go through
+    /// `timeline_migrate` rather than assuming this is always
+    /// `SCHEMA_VERSION`; `deny_unknown_fields` here means a record written by
+    /// a newer version of this tool with extra fields will fail to parse
+    /// directly instead of silently dropping data, which is the signal to
+    /// run it through the migrator (or upgrade the reader).
+    pub version: u32,
     /// Source revision this record contains details for.
     pub source_rev: String,
     /// The syntax revision that corresponds to that source revision.
@@ -15,7 +33,10 @@ pub struct DetailRecordRef {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct SummaryRecordRef {
+    /// Schema version this record was written at; see `DetailRecordRef::version`.
+    pub version: u32,
     /// List of all of the source revisions whose data is aggregated into this
     /// summary record ordered from newest to oldest.  It's possible to have a
     /// length of 1 as our policy is to aggregate at a week-based granularity
@@ -38,10 +59,18 @@ pub struct SummaryRecordRef {
     /// Summary records should never overlap, so sorting by the tuple should
     /// work acceptably.
     pub iso_week_range: (u16, u8, u8),
+
+    /// Conventional-Commit classification for each entry in `source_revs`,
+    /// same order, same length -- except for records migrated up from v3 or
+    /// earlier, where it is empty (see `timeline_migrate`).  Lets a reader facet the aggregated
+    /// `ChangeKind`/token-delta counts by commit intent (see
+    /// `commit_classify::FacetedTokenDeltaRollup`) instead of only ever
+    /// seeing an unfaceted total.
+    pub revision_classifications: Vec<CommitClassification>,
 }
 
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct TokenDeltaDetails {
     /// Number of times this token was present in a "+" diff delta that could
     /// not be attributed to a matching syntactically bound "-" and thereby
@@ -101,18 +130,31 @@ pub enum ChangeKind {
 /// Summarized changes at symbol granularity, with the "pretty" being assumed to
 /// be stored externally in a map key that owns this value or in a wrapper if a
 /// map is not involved.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct SymbolSyntaxDelta {
     pub change: ChangeKind,
 
     /// Changes to tokens within the owning scope corresponding to this pretty
     /// identifier.
     pub token_changes: BTreeMap<String, TokenDeltaDetails>,
+
+    /// When `change` is `ChangeKind::Evolved`, the pretty identifier of the
+    /// symbol this one is inferred to have evolved from (see
+    /// `symbol_evolution::infer_symbol_evolutions`).  `None` if we marked this
+    /// `Evolved` without being able to find a confident predecessor, on the
+    /// removed (predecessor) side of an inferred evolution, or for any other
+    /// `ChangeKind`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub evolved_from_symbol: Option<String>,
 }
 
 /// Holds aggregated changes to symbols.
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct SymbolSyntaxDeltaGroup {
+    /// Schema version this record was written at; see `DetailRecordRef::version`.
+    pub version: u32,
+
     /// Maps symbols to the deltas observed related to the symbol.  Note that
     /// "%" is a sentinel corresponding to there being no scope
     /// which is arbitrarily derived from prior blame processing logic.
@@ -0,0 +1,343 @@
+//! Incremental edit-encoding of `SymbolSyntaxDeltaGroup` between adjacent
+//! timeline revisions, borrowing the shape of LSP's semantic-tokens-delta
+//! request: rather than re-serializing the whole group every revision, emit
+//! an edit set of `{start_index, delete_count, inserted_entries}` operations
+//! over the sorted `symbol_deltas` key list, plus a `result_id` naming the
+//! base the edits were computed against.  A consumer holding that base group
+//! applies the edits to reconstruct the full group; if it doesn't have a
+//! group stored under `base_result_id`, it must fall back to requesting the
+//! full group instead of guessing.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::timeline_common::{ChangeKind, SymbolSyntaxDelta, SymbolSyntaxDeltaGroup, TokenDeltaDetails};
+
+/// One entry of `SymbolDeltaEdit::inserted_entries`.  Most entries are
+/// wholesale replacements, but when a symbol already existed in the base
+/// group and is merely `Changed`, we only ship the `token_changes` keys that
+/// actually differ rather than the whole `SymbolSyntaxDelta`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum SymbolDeltaEntry {
+    Full {
+        symbol: String,
+        delta: SymbolSyntaxDelta,
+    },
+    TokenChangesPatch {
+        symbol: String,
+        /// Keys present here are only the `token_changes` entries that
+        /// differ from the base group's entry for this symbol; unchanged
+        /// keys are omitted so the patch stays small.
+        token_patch: BTreeMap<String, TokenDeltaDetails>,
+        /// `token_changes` keys the base group's entry had that `new` no
+        /// longer has at all, so `apply_entry` knows to delete rather than
+        /// just insert/overwrite. Without this a token dropped between
+        /// adjacent revisions would silently stick around forever in every
+        /// reconstructed group downstream.
+        removed_tokens: Vec<String>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SymbolDeltaEdit {
+    /// Index into the sorted `symbol_deltas` key list of the base group
+    /// (after any earlier edits in this same set have been applied) where
+    /// the deletion/insertion happens.
+    pub start_index: usize,
+    /// Number of consecutive base entries, starting at `start_index`, to
+    /// remove before splicing in `inserted_entries`.
+    pub delete_count: usize,
+    pub inserted_entries: Vec<SymbolDeltaEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SymbolSyntaxDeltaGroupEdits {
+    /// The result id of the base group these edits were computed against.
+    /// If the consumer doesn't have a group stored under this id, it must
+    /// request the full `new` group instead of trying to apply these edits.
+    pub base_result_id: String,
+    /// The result id the group will have once these edits are applied; this
+    /// becomes the `base_result_id` of the next call.
+    pub result_id: String,
+    pub edits: Vec<SymbolDeltaEdit>,
+}
+
+/// Computes the edit set that turns `base` into `new`.  `symbol_deltas` being
+/// a `BTreeMap` is load-bearing here: iteration order is the sorted key
+/// order, so it's the canonical index space both sides agree on without
+/// either side needing to separately transmit or reconstruct an ordering.
+pub fn encode_symbol_syntax_delta_group_edits(
+    base: &SymbolSyntaxDeltaGroup,
+    base_result_id: &str,
+    new: &SymbolSyntaxDeltaGroup,
+    new_result_id: &str,
+) -> SymbolSyntaxDeltaGroupEdits {
+    let base_entries: Vec<(&String, &SymbolSyntaxDelta)> = base.symbol_deltas.iter().collect();
+    let new_entries: Vec<(&String, &SymbolSyntaxDelta)> = new.symbol_deltas.iter().collect();
+
+    let mut prefix = 0;
+    while prefix < base_entries.len()
+        && prefix < new_entries.len()
+        && base_entries[prefix] == new_entries[prefix]
+    {
+        prefix += 1;
+    }
+
+    let mut suffix = 0;
+    while suffix < base_entries.len() - prefix
+        && suffix < new_entries.len() - prefix
+        && base_entries[base_entries.len() - 1 - suffix] == new_entries[new_entries.len() - 1 - suffix]
+    {
+        suffix += 1;
+    }
+
+    let delete_count = base_entries.len() - prefix - suffix;
+    let changed_new = &new_entries[prefix..new_entries.len() - suffix];
+
+    let inserted_entries = changed_new
+        .iter()
+        .map(|(symbol, delta)| encode_entry(base, symbol, delta))
+        .collect();
+
+    let edits = if delete_count == 0 && inserted_entries.is_empty() {
+        Vec::new()
+    } else {
+        vec![SymbolDeltaEdit {
+            start_index: prefix,
+            delete_count,
+            inserted_entries,
+        }]
+    };
+
+    SymbolSyntaxDeltaGroupEdits {
+        base_result_id: base_result_id.to_string(),
+        result_id: new_result_id.to_string(),
+        edits,
+    }
+}
+
+fn encode_entry(base: &SymbolSyntaxDeltaGroup, symbol: &str, delta: &SymbolSyntaxDelta) -> SymbolDeltaEntry {
+    if delta.change == ChangeKind::Changed {
+        if let Some(base_delta) = base.symbol_deltas.get(symbol) {
+            let mut token_patch = BTreeMap::new();
+            for (token, details) in &delta.token_changes {
+                if base_delta.token_changes.get(token) != Some(details) {
+                    token_patch.insert(token.clone(), details.clone());
+                }
+            }
+            let removed_tokens = base_delta
+                .token_changes
+                .keys()
+                .filter(|token| !delta.token_changes.contains_key(*token))
+                .cloned()
+                .collect();
+            return SymbolDeltaEntry::TokenChangesPatch {
+                symbol: symbol.to_string(),
+                token_patch,
+                removed_tokens,
+            };
+        }
+    }
+    SymbolDeltaEntry::Full {
+        symbol: symbol.to_string(),
+        delta: delta.clone(),
+    }
+}
+
+#[derive(Debug)]
+pub enum ApplyEditsError {
+    /// The caller's `base` wasn't stamped with the `base_result_id` the
+    /// edits were computed against; it must fetch the full group instead.
+    BaseMismatch,
+    /// A `TokenChangesPatch` named a symbol that isn't present in `base`, so
+    /// there's nothing to patch; the edit stream is corrupt or was computed
+    /// against a different base than the caller has.
+    PatchTargetMissing(String),
+}
+
+/// Applies a previously-computed edit set to `base` (which the caller must
+/// have confirmed is stamped `edits.base_result_id`) to reconstruct the full
+/// `new` group.
+pub fn apply_symbol_syntax_delta_group_edits(
+    base: &SymbolSyntaxDeltaGroup,
+    base_result_id: &str,
+    edits: &SymbolSyntaxDeltaGroupEdits,
+) -> Result<SymbolSyntaxDeltaGroup, ApplyEditsError> {
+    if base_result_id != edits.base_result_id {
+        return Err(ApplyEditsError::BaseMismatch);
+    }
+
+    let mut entries: Vec<(String, SymbolSyntaxDelta)> = base
+        .symbol_deltas
+        .iter()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+
+    // Edits are expressed against the index space as it existed when they
+    // were computed; today we only ever emit a single edit, but apply them
+    // in order in case a future encoder emits more than one.
+    for edit in &edits.edits {
+        let mut replacement = Vec::with_capacity(edit.inserted_entries.len());
+        for inserted in &edit.inserted_entries {
+            replacement.push(apply_entry(base, inserted)?);
+        }
+        let end = edit.start_index + edit.delete_count;
+        entries.splice(edit.start_index..end, replacement);
+    }
+
+    Ok(SymbolSyntaxDeltaGroup {
+        version: base.version,
+        symbol_deltas: entries.into_iter().collect(),
+    })
+}
+
+fn apply_entry(
+    base: &SymbolSyntaxDeltaGroup,
+    entry: &SymbolDeltaEntry,
+) -> Result<(String, SymbolSyntaxDelta), ApplyEditsError> {
+    match entry {
+        SymbolDeltaEntry::Full { symbol, delta } => Ok((symbol.clone(), delta.clone())),
+        SymbolDeltaEntry::TokenChangesPatch {
+            symbol,
+            token_patch,
+            removed_tokens,
+        } => {
+            let base_delta = base
+                .symbol_deltas
+                .get(symbol)
+                .ok_or_else(|| ApplyEditsError::PatchTargetMissing(symbol.clone()))?;
+            let mut token_changes = base_delta.token_changes.clone();
+            for token in removed_tokens {
+                token_changes.remove(token);
+            }
+            for (token, details) in token_patch {
+                token_changes.insert(token.clone(), details.clone());
+            }
+            Ok((
+                symbol.clone(),
+                SymbolSyntaxDelta {
+                    change: ChangeKind::Changed,
+                    token_changes,
+                    evolved_from_symbol: base_delta.evolved_from_symbol.clone(),
+                },
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delta(change: ChangeKind, token_changes: &[(&str, u32)]) -> SymbolSyntaxDelta {
+        SymbolSyntaxDelta {
+            change,
+            token_changes: token_changes
+                .iter()
+                .map(|(name, added)| {
+                    (
+                        name.to_string(),
+                        TokenDeltaDetails {
+                            added: *added,
+                            moved: 0,
+                            evolved_from: 0,
+                            removed: 0,
+                        },
+                    )
+                })
+                .collect(),
+            evolved_from_symbol: None,
+        }
+    }
+
+    fn group(version: u32, entries: Vec<(&str, SymbolSyntaxDelta)>) -> SymbolSyntaxDeltaGroup {
+        SymbolSyntaxDeltaGroup {
+            version,
+            symbol_deltas: entries.into_iter().map(|(k, v)| (k.to_string(), v)).collect(),
+        }
+    }
+
+    fn roundtrip(base: &SymbolSyntaxDeltaGroup, new: &SymbolSyntaxDeltaGroup) {
+        let edits = encode_symbol_syntax_delta_group_edits(base, "base", new, "new");
+        let applied = apply_symbol_syntax_delta_group_edits(base, "base", &edits).unwrap();
+        assert_eq!(applied.symbol_deltas, new.symbol_deltas);
+    }
+
+    #[test]
+    fn no_changes_produces_no_edits() {
+        let base = group(4, vec![("a::b", delta(ChangeKind::Added, &[("tok", 1)]))]);
+        let edits = encode_symbol_syntax_delta_group_edits(&base, "base", &base, "base");
+        assert!(edits.edits.is_empty());
+        roundtrip(&base, &base);
+    }
+
+    #[test]
+    fn added_symbol_round_trips() {
+        let base = group(4, vec![("a::b", delta(ChangeKind::Added, &[("tok", 1)]))]);
+        let new = group(
+            4,
+            vec![
+                ("a::b", delta(ChangeKind::Added, &[("tok", 1)])),
+                ("a::c", delta(ChangeKind::Added, &[("tok2", 1)])),
+            ],
+        );
+        roundtrip(&base, &new);
+    }
+
+    #[test]
+    fn changed_symbol_with_added_token_patches_instead_of_full() {
+        let base = group(4, vec![("a::b", delta(ChangeKind::Changed, &[("tok", 1)]))]);
+        let new = group(
+            4,
+            vec![(
+                "a::b",
+                delta(ChangeKind::Changed, &[("tok", 1), ("tok2", 2)]),
+            )],
+        );
+        let edits = encode_symbol_syntax_delta_group_edits(&base, "base", &new, "new");
+        match &edits.edits[0].inserted_entries[0] {
+            SymbolDeltaEntry::TokenChangesPatch {
+                token_patch,
+                removed_tokens,
+                ..
+            } => {
+                assert_eq!(token_patch.len(), 1);
+                assert!(token_patch.contains_key("tok2"));
+                assert!(removed_tokens.is_empty());
+            }
+            other => panic!("expected TokenChangesPatch, got {:?}", other),
+        }
+        roundtrip(&base, &new);
+    }
+
+    #[test]
+    fn changed_symbol_with_removed_token_round_trips() {
+        let base = group(
+            4,
+            vec![(
+                "a::b",
+                delta(ChangeKind::Changed, &[("tok", 1), ("tok2", 2)]),
+            )],
+        );
+        let new = group(4, vec![("a::b", delta(ChangeKind::Changed, &[("tok", 1)]))]);
+
+        let edits = encode_symbol_syntax_delta_group_edits(&base, "base", &new, "new");
+        match &edits.edits[0].inserted_entries[0] {
+            SymbolDeltaEntry::TokenChangesPatch { removed_tokens, .. } => {
+                assert_eq!(removed_tokens, &vec!["tok2".to_string()]);
+            }
+            other => panic!("expected TokenChangesPatch, got {:?}", other),
+        }
+        roundtrip(&base, &new);
+    }
+
+    #[test]
+    fn apply_rejects_mismatched_base_result_id() {
+        let base = group(4, vec![("a::b", delta(ChangeKind::Added, &[("tok", 1)]))]);
+        let edits = encode_symbol_syntax_delta_group_edits(&base, "base", &base, "base");
+        let err = apply_symbol_syntax_delta_group_edits(&base, "wrong-id", &edits).unwrap_err();
+        assert!(matches!(err, ApplyEditsError::BaseMismatch));
+    }
+}
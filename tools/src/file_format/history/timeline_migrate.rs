@@ -0,0 +1,350 @@
+//! Forward-migration for the versioned records defined in `timeline_common`.
+//!
+//! Readers should never hand-roll "if the field is missing, assume X"; that's
+//! exactly the kind of silent drift this module exists to prevent.  Instead,
+//! sniff the `version` tag out of the raw JSON, and if it's behind
+//! `SCHEMA_VERSION`, walk it forward one step at a time through the `v1`/`v2`
+//! historical shapes below, the same way the dump-reading side of things
+//! keeps separate `reader/v3`, `v4`, `v5` modules and chains them rather than
+//! trying to write one reader that understands every vintage of the format at
+//! once.
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::commit_classify::CommitClassification;
+use super::timeline_common::{DetailRecordRef, SummaryRecordRef, SymbolSyntaxDeltaGroup, SCHEMA_VERSION};
+
+#[derive(Debug)]
+pub enum MigrateError {
+    /// The JSON's `version` field wasn't a non-negative integer, or it was
+    /// literally `0` -- there's no version-0 shape in `v1`/`v2`/etc, so `0`
+    /// can only mean a writer bug or corruption, not real legacy data. (A
+    /// record with no `version` field at all is read as v1; see
+    /// `sniff_version`.)
+    MissingVersion,
+    /// The record claims a version newer than `SCHEMA_VERSION`; we have no
+    /// upgrade path for the future, so refuse rather than guess.
+    FutureVersion(u32),
+    Json(serde_json::Error),
+}
+
+impl From<serde_json::Error> for MigrateError {
+    fn from(err: serde_json::Error) -> Self {
+        MigrateError::Json(err)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct VersionTag {
+    version: u32,
+}
+
+/// A record with no `version` field at all is real legacy v1 data -- see the
+/// `mod v1` doc comment -- so that case defaults to version 1 rather than
+/// erroring; `MissingVersion` is reserved for a `version` field that's
+/// present but isn't a non-negative integer, or is the never-assigned `0`,
+/// both of which are actually corrupt rather than real legacy data.
+fn sniff_version(raw: &Value) -> Result<u32, MigrateError> {
+    match raw.get("version") {
+        None => Ok(1),
+        Some(_) => {
+            let tag: VersionTag =
+                serde_json::from_value(raw.clone()).map_err(|_| MigrateError::MissingVersion)?;
+            if tag.version == 0 {
+                return Err(MigrateError::MissingVersion);
+            }
+            Ok(tag.version)
+        }
+    }
+}
+
+/// Historical v1 shapes.  v1 predates both the `version` field (implicitly
+/// version 1) and the `iso_date`/`pred_timeline_rev` fields that v2 added.
+mod v1 {
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    pub struct DetailRecordRef {
+        pub source_rev: String,
+        pub syntax_rev: String,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct SummaryRecordRef {
+        pub source_revs: Vec<String>,
+        pub iso_week_range: (u16, u8, u8),
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct SymbolSyntaxDeltaGroup {
+        pub symbol_deltas: std::collections::BTreeMap<String, super::super::timeline_common::SymbolSyntaxDelta>,
+    }
+}
+
+/// v2 added the `version` field itself plus `iso_date` (detail) and
+/// `pred_timeline_rev` (summary), but not yet `deny_unknown_fields`.
+mod v2 {
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    pub struct DetailRecordRef {
+        pub version: u32,
+        pub source_rev: String,
+        pub syntax_rev: String,
+        pub iso_date: String,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct SummaryRecordRef {
+        pub version: u32,
+        pub source_revs: Vec<String>,
+        pub pred_timeline_rev: String,
+        pub iso_week_range: (u16, u8, u8),
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub struct SymbolSyntaxDeltaGroup {
+        pub version: u32,
+        pub symbol_deltas: std::collections::BTreeMap<String, super::super::timeline_common::SymbolSyntaxDelta>,
+    }
+}
+
+fn upgrade_detail_v1_to_v2(old: v1::DetailRecordRef) -> v2::DetailRecordRef {
+    v2::DetailRecordRef {
+        version: 2,
+        source_rev: old.source_rev,
+        syntax_rev: old.syntax_rev,
+        // v1 didn't record this; callers re-deriving from git history should
+        // overwrite it, but we can't invent a date here.
+        iso_date: String::new(),
+    }
+}
+
+fn upgrade_detail_v2_to_v3(old: v2::DetailRecordRef) -> v2::DetailRecordRef {
+    // v3 only added `#[serde(deny_unknown_fields)]` to the live struct; the
+    // wire shape is unchanged, so this step is just a version-stamp bump.
+    v2::DetailRecordRef {
+        version: 3,
+        ..old
+    }
+}
+
+fn upgrade_detail_v3_to_v4(old: v2::DetailRecordRef) -> DetailRecordRef {
+    // v4 added `SummaryRecordRef::revision_classifications`, which doesn't
+    // touch the detail record shape at all; again, just the version stamp.
+    DetailRecordRef {
+        version: 4,
+        source_rev: old.source_rev,
+        syntax_rev: old.syntax_rev,
+        iso_date: old.iso_date,
+    }
+}
+
+/// Migrate a raw detail record JSON value to the current `DetailRecordRef`
+/// shape, running whichever of the ordered `vN_to_vN+1` steps above are
+/// needed.  This is the only sanctioned way to read a detail record whose
+/// `version` might be behind `SCHEMA_VERSION`.
+pub fn migrate_detail_record(raw: Value) -> Result<DetailRecordRef, MigrateError> {
+    let version = sniff_version(&raw)?;
+    if version > SCHEMA_VERSION {
+        return Err(MigrateError::FutureVersion(version));
+    }
+    let mut cur_v2 = match version {
+        1 => {
+            let old: v1::DetailRecordRef = serde_json::from_value(raw)?;
+            upgrade_detail_v1_to_v2(old)
+        }
+        2 => serde_json::from_value(raw)?,
+        3 => serde_json::from_value(raw)?,
+        4 => return Ok(serde_json::from_value(raw)?),
+        _ => unreachable!("version <= SCHEMA_VERSION and > 3 handled above"),
+    };
+    if cur_v2.version < 2 {
+        cur_v2.version = 2;
+    }
+    if cur_v2.version < 3 {
+        cur_v2 = upgrade_detail_v2_to_v3(cur_v2);
+    }
+    Ok(upgrade_detail_v3_to_v4(cur_v2))
+}
+
+fn upgrade_summary_v1_to_v2(old: v1::SummaryRecordRef) -> v2::SummaryRecordRef {
+    v2::SummaryRecordRef {
+        version: 2,
+        source_revs: old.source_revs,
+        // v1 had no notion of chaining to a predecessor timeline revision.
+        pred_timeline_rev: String::new(),
+        iso_week_range: old.iso_week_range,
+    }
+}
+
+fn upgrade_summary_v2_to_v3(old: v2::SummaryRecordRef) -> v2::SummaryRecordRef {
+    v2::SummaryRecordRef { version: 3, ..old }
+}
+
+/// v3 summary records predate Conventional-Commit classification; we have no
+/// commit messages to re-parse at migration time, so `revision_classifications`
+/// comes back empty and the caller is expected to treat that as "unclassified"
+/// rather than "zero revisions".
+fn upgrade_summary_v3_to_v4(old: v2::SummaryRecordRef) -> SummaryRecordRef {
+    SummaryRecordRef {
+        version: 4,
+        source_revs: old.source_revs,
+        pred_timeline_rev: old.pred_timeline_rev,
+        iso_week_range: old.iso_week_range,
+        revision_classifications: Vec::new(),
+    }
+}
+
+pub fn migrate_summary_record(raw: Value) -> Result<SummaryRecordRef, MigrateError> {
+    let version = sniff_version(&raw)?;
+    if version > SCHEMA_VERSION {
+        return Err(MigrateError::FutureVersion(version));
+    }
+    let mut cur_v2 = match version {
+        1 => {
+            let old: v1::SummaryRecordRef = serde_json::from_value(raw)?;
+            upgrade_summary_v1_to_v2(old)
+        }
+        2 => serde_json::from_value(raw)?,
+        3 => serde_json::from_value(raw)?,
+        4 => return Ok(serde_json::from_value(raw)?),
+        _ => unreachable!("version <= SCHEMA_VERSION and > 3 handled above"),
+    };
+    if cur_v2.version < 3 {
+        cur_v2 = upgrade_summary_v2_to_v3(cur_v2);
+    }
+    Ok(upgrade_summary_v3_to_v4(cur_v2))
+}
+
+fn upgrade_delta_group_v1_to_v2(old: v1::SymbolSyntaxDeltaGroup) -> v2::SymbolSyntaxDeltaGroup {
+    v2::SymbolSyntaxDeltaGroup {
+        version: 2,
+        symbol_deltas: old.symbol_deltas,
+    }
+}
+
+fn upgrade_delta_group_v2_to_v3(old: v2::SymbolSyntaxDeltaGroup) -> v2::SymbolSyntaxDeltaGroup {
+    v2::SymbolSyntaxDeltaGroup { version: 3, ..old }
+}
+
+fn upgrade_delta_group_v3_to_v4(old: v2::SymbolSyntaxDeltaGroup) -> SymbolSyntaxDeltaGroup {
+    // Unaffected by the v4 (Conventional-Commit facet) bump; version-stamp
+    // bump only.
+    SymbolSyntaxDeltaGroup {
+        version: 4,
+        symbol_deltas: old.symbol_deltas,
+    }
+}
+
+pub fn migrate_symbol_syntax_delta_group(raw: Value) -> Result<SymbolSyntaxDeltaGroup, MigrateError> {
+    let version = sniff_version(&raw)?;
+    if version > SCHEMA_VERSION {
+        return Err(MigrateError::FutureVersion(version));
+    }
+    let mut cur_v2 = match version {
+        1 => {
+            let old: v1::SymbolSyntaxDeltaGroup = serde_json::from_value(raw)?;
+            upgrade_delta_group_v1_to_v2(old)
+        }
+        2 => serde_json::from_value(raw)?,
+        3 => serde_json::from_value(raw)?,
+        4 => return Ok(serde_json::from_value(raw)?),
+        _ => unreachable!("version <= SCHEMA_VERSION and > 3 handled above"),
+    };
+    if cur_v2.version < 3 {
+        cur_v2 = upgrade_delta_group_v2_to_v3(cur_v2);
+    }
+    Ok(upgrade_delta_group_v3_to_v4(cur_v2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn missing_version_defaults_to_v1() {
+        assert_eq!(sniff_version(&json!({"foo": "bar"})).unwrap(), 1);
+    }
+
+    #[test]
+    fn version_zero_is_missing_version() {
+        assert!(matches!(
+            sniff_version(&json!({"version": 0})),
+            Err(MigrateError::MissingVersion)
+        ));
+    }
+
+    #[test]
+    fn non_integer_version_is_missing_version() {
+        assert!(matches!(
+            sniff_version(&json!({"version": "nope"})),
+            Err(MigrateError::MissingVersion)
+        ));
+    }
+
+    #[test]
+    fn future_version_is_rejected() {
+        let raw = json!({
+            "version": SCHEMA_VERSION + 1,
+            "source_rev": "deadbeef",
+            "syntax_rev": "deadbeef",
+            "iso_date": "2026-01-01",
+        });
+        assert!(matches!(
+            migrate_detail_record(raw),
+            Err(MigrateError::FutureVersion(v)) if v == SCHEMA_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn v1_detail_record_migrates_to_current() {
+        let raw = json!({
+            "source_rev": "deadbeef",
+            "syntax_rev": "feedface",
+        });
+        let migrated = migrate_detail_record(raw).unwrap();
+        assert_eq!(migrated.version, SCHEMA_VERSION);
+        assert_eq!(migrated.source_rev, "deadbeef");
+        assert_eq!(migrated.syntax_rev, "feedface");
+        assert_eq!(migrated.iso_date, "");
+    }
+
+    #[test]
+    fn v1_summary_record_migrates_to_current() {
+        let raw = json!({
+            "source_revs": ["deadbeef"],
+            "iso_week_range": [2026, 1, 1],
+        });
+        let migrated = migrate_summary_record(raw).unwrap();
+        assert_eq!(migrated.version, SCHEMA_VERSION);
+        assert_eq!(migrated.source_revs, vec!["deadbeef".to_string()]);
+        assert_eq!(migrated.pred_timeline_rev, "");
+        assert!(migrated.revision_classifications.is_empty());
+    }
+
+    #[test]
+    fn v1_symbol_syntax_delta_group_migrates_to_current() {
+        let raw = json!({
+            "symbol_deltas": {},
+        });
+        let migrated = migrate_symbol_syntax_delta_group(raw).unwrap();
+        assert_eq!(migrated.version, SCHEMA_VERSION);
+        assert!(migrated.symbol_deltas.is_empty());
+    }
+
+    #[test]
+    fn current_version_round_trips_unchanged() {
+        let raw = json!({
+            "version": SCHEMA_VERSION,
+            "source_revs": ["deadbeef"],
+            "pred_timeline_rev": "cafebabe",
+            "iso_week_range": [2026, 1, 1],
+            "revision_classifications": [],
+        });
+        let migrated = migrate_summary_record(raw).unwrap();
+        assert_eq!(migrated.pred_timeline_rev, "cafebabe");
+    }
+}
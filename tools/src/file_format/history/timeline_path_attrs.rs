@@ -0,0 +1,270 @@
+//! A `.gitattributes`-style include/exclude mechanism (modeled on
+//! `gix-attributes`) controlling which paths feed `TokenDeltaDetails`
+//! aggregation, so generated/vendored/minified files don't pollute weekly
+//! churn counts.
+//!
+//! Rules are parsed out of an ordered list of lines of the form:
+//!
+//! ```text
+//! # comment
+//! *.min.js mozsearch-timeline=skip
+//! /vendor/** mozsearch-timeline=skip
+//! /vendor/allowlisted.js mozsearch-timeline=weight:0.2
+//! !/vendor/hand-maintained/** mozsearch-timeline=skip
+//! ```
+//!
+//! Patterns follow the usual `.gitattributes`/`.gitignore` conventions: a
+//! leading `/` anchors the pattern to the root instead of letting it match
+//! at any depth, `**` matches zero or more path segments, and a leading `!`
+//! negates the rule (resetting the path back to the default attribute state
+//! rather than literally inverting the match). Later rules win over earlier
+//! ones for a given path, same as real `.gitattributes` processing.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PathAttrValue {
+    /// `mozsearch-timeline=skip` -- exclude the path from aggregation
+    /// entirely.
+    Skip,
+    /// `mozsearch-timeline=weight:N` -- scale add/move/remove counts by `N`
+    /// instead of excluding the path outright.
+    Weight(f64),
+}
+
+struct PathAttrRule {
+    negate: bool,
+    anchored: bool,
+    /// Pattern with any leading `!` and `/` already stripped, split into
+    /// path segments for matching.
+    segments: Vec<String>,
+    attr: PathAttrValue,
+}
+
+/// The resolved attribute state for a path after applying every matching
+/// rule in order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ResolvedPathAttr {
+    pub skip: bool,
+    pub weight: f64,
+}
+
+impl Default for ResolvedPathAttr {
+    fn default() -> Self {
+        ResolvedPathAttr {
+            skip: false,
+            weight: 1.0,
+        }
+    }
+}
+
+pub struct PathAttrMatcher {
+    rules: Vec<PathAttrRule>,
+}
+
+impl PathAttrMatcher {
+    pub fn parse(config: &str) -> Self {
+        let mut rules = Vec::new();
+        for line in config.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(2, char::is_whitespace);
+            let pattern_part = match parts.next() {
+                Some(p) if !p.is_empty() => p,
+                _ => continue,
+            };
+            let attr_part = match parts.next() {
+                Some(p) => p.trim(),
+                None => continue,
+            };
+            let attr = match parse_attr_value(attr_part) {
+                Some(attr) => attr,
+                None => continue,
+            };
+
+            let (negate, pattern_part) = match pattern_part.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, pattern_part),
+            };
+            let (anchored, pattern_part) = match pattern_part.strip_prefix('/') {
+                Some(rest) => (true, rest),
+                None => (false, pattern_part),
+            };
+
+            rules.push(PathAttrRule {
+                negate,
+                anchored,
+                segments: pattern_part.split('/').map(str::to_string).collect(),
+                attr,
+            });
+        }
+        PathAttrMatcher { rules }
+    }
+
+    /// Returns the resolved attribute state for `path`, applying every
+    /// matching rule in file order so that a later rule overrides an earlier
+    /// one (last-match-wins), with `!`-prefixed rules resetting the path back
+    /// to the unattributed default instead of excluding/weighting it.
+    pub fn resolve(&self, path: &str) -> ResolvedPathAttr {
+        let path_segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let mut result = ResolvedPathAttr::default();
+        for rule in &self.rules {
+            if !rule_matches(rule, &path_segments) {
+                continue;
+            }
+            if rule.negate {
+                result = ResolvedPathAttr::default();
+            } else {
+                match rule.attr {
+                    PathAttrValue::Skip => result.skip = true,
+                    PathAttrValue::Weight(w) => {
+                        result.skip = false;
+                        result.weight = w;
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+fn parse_attr_value(s: &str) -> Option<PathAttrValue> {
+    let (key, value) = s.split_once('=')?;
+    if key.trim() != "mozsearch-timeline" {
+        return None;
+    }
+    let value = value.trim();
+    if value == "skip" {
+        Some(PathAttrValue::Skip)
+    } else {
+        value
+            .strip_prefix("weight:")
+            .and_then(|w| w.parse::<f64>().ok())
+            .map(PathAttrValue::Weight)
+    }
+}
+
+fn rule_matches(rule: &PathAttrRule, path_segments: &[&str]) -> bool {
+    if rule.anchored {
+        segments_match(&rule.segments, path_segments)
+    } else {
+        // An unanchored pattern may match starting at any depth, mirroring
+        // gitignore's behavior for patterns without a leading slash.
+        (0..=path_segments.len()).any(|start| segments_match(&rule.segments, &path_segments[start..]))
+    }
+}
+
+fn segments_match(pattern: &[String], path: &[&str]) -> bool {
+    match pattern.first() {
+        None => path.is_empty(),
+        Some(seg) if seg == "**" => {
+            // `**` matches zero or more whole path segments.
+            (0..=path.len()).any(|skip| segments_match(&pattern[1..], &path[skip..]))
+        }
+        Some(seg) => {
+            !path.is_empty() && segment_glob_match(seg, path[0]) && segments_match(&pattern[1..], &path[1..])
+        }
+    }
+}
+
+/// Matches a single path segment against a single pattern segment that may
+/// contain `*` (matching any run of characters, not crossing a `/`) and `?`
+/// (matching exactly one character).
+fn segment_glob_match(pattern: &str, segment: &str) -> bool {
+    fn helper(pattern: &[u8], segment: &[u8]) -> bool {
+        match (pattern.first(), segment.first()) {
+            (None, None) => true,
+            (Some(b'*'), _) => {
+                helper(&pattern[1..], segment) || (!segment.is_empty() && helper(pattern, &segment[1..]))
+            }
+            (Some(b'?'), Some(_)) => helper(&pattern[1..], &segment[1..]),
+            (Some(p), Some(s)) if p == s => helper(&pattern[1..], &segment[1..]),
+            _ => false,
+        }
+    }
+    helper(pattern.as_bytes(), segment.as_bytes())
+}
+
+/// Scales a `TokenDeltaDetails`'s counts by `weight`, rounding to the nearest
+/// integer.  Used when a path's resolved attribute state carries a weight
+/// other than `1.0` instead of an outright `skip`.
+pub fn scale_token_delta_details(
+    details: &super::timeline_common::TokenDeltaDetails,
+    weight: f64,
+) -> super::timeline_common::TokenDeltaDetails {
+    let scale = |count: u32| ((count as f64) * weight).round().max(0.0) as u32;
+    super::timeline_common::TokenDeltaDetails {
+        added: scale(details.added),
+        moved: scale(details.moved),
+        evolved_from: scale(details.evolved_from),
+        removed: scale(details.removed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unanchored_glob_matches_at_any_depth() {
+        let matcher = PathAttrMatcher::parse("*.min.js mozsearch-timeline=skip");
+        assert!(matcher.resolve("vendor/lib.min.js").skip);
+        assert!(matcher.resolve("a/b/c/lib.min.js").skip);
+        assert!(!matcher.resolve("src/lib.js").skip);
+    }
+
+    #[test]
+    fn anchored_pattern_only_matches_from_root() {
+        let matcher = PathAttrMatcher::parse("/vendor/** mozsearch-timeline=skip");
+        assert!(matcher.resolve("vendor/thing.js").skip);
+        assert!(matcher.resolve("vendor/nested/thing.js").skip);
+        assert!(!matcher.resolve("src/vendor/thing.js").skip);
+    }
+
+    #[test]
+    fn later_rule_overrides_earlier_for_same_path() {
+        let matcher = PathAttrMatcher::parse(
+            "/vendor/** mozsearch-timeline=skip\n/vendor/allowlisted.js mozsearch-timeline=weight:0.2",
+        );
+        let resolved = matcher.resolve("vendor/allowlisted.js");
+        assert!(!resolved.skip);
+        assert_eq!(resolved.weight, 0.2);
+    }
+
+    #[test]
+    fn negation_resets_to_default_instead_of_inverting() {
+        let matcher = PathAttrMatcher::parse(
+            "/vendor/** mozsearch-timeline=skip\n!/vendor/hand-maintained/** mozsearch-timeline=skip",
+        );
+        let resolved = matcher.resolve("vendor/hand-maintained/thing.js");
+        assert!(!resolved.skip);
+        assert_eq!(resolved.weight, 1.0);
+    }
+
+    #[test]
+    fn unmatched_path_gets_default_attrs() {
+        let matcher = PathAttrMatcher::parse("/vendor/** mozsearch-timeline=skip");
+        assert_eq!(matcher.resolve("src/main.rs"), ResolvedPathAttr::default());
+    }
+
+    #[test]
+    fn comments_and_blank_lines_and_bad_values_are_ignored() {
+        let matcher = PathAttrMatcher::parse("# a comment\n\n*.log mozsearch-timeline=bogus\n*.log mozsearch-timeline=skip");
+        assert!(matcher.resolve("out.log").skip);
+    }
+
+    #[test]
+    fn scale_token_delta_details_rounds_and_floors_at_zero() {
+        let details = super::super::timeline_common::TokenDeltaDetails {
+            added: 5,
+            moved: 3,
+            evolved_from: 1,
+            removed: 2,
+        };
+        let scaled = scale_token_delta_details(&details, 0.2);
+        assert_eq!(scaled.added, 1);
+        assert_eq!(scaled.moved, 1);
+        assert_eq!(scaled.evolved_from, 0);
+        assert_eq!(scaled.removed, 0);
+    }
+}
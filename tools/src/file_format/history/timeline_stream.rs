@@ -0,0 +1,97 @@
+//! Async streaming walk over the summary/detail timeline chain.
+//!
+//! Without this, following history means manually dereferencing
+//! `SummaryRecordRef::pred_timeline_rev` and loading each record by hand.
+//! This is modeled after a paged GitHub API client: callers get back an
+//! `impl Stream<Item = Result<...>>` and can `.next().await` their way
+//! backward through history with natural backpressure, rather than being
+//! handed a `Vec` that requires loading everything up front.
+
+use async_stream::stream;
+use futures_core::Stream;
+
+use super::timeline_common::{DetailRecordRef, SummaryRecordRef};
+
+#[derive(Debug)]
+pub enum TimelineStreamError {
+    /// The backing store has nothing for the referenced timeline revision;
+    /// the chain is broken (or we raced a rewrite of history).
+    MissingTimelineRev(String),
+    Fetch(String),
+}
+
+/// One item yielded while walking the chain.  Walking transitions from
+/// `Summary` records into the `Detail` records they aggregate once we cross
+/// into the revision range the summary covers.
+#[derive(Debug)]
+pub enum TimelineChainItem {
+    Summary(SummaryRecordRef),
+    Detail(DetailRecordRef),
+}
+
+/// Abstracts over however the caller actually loads records (reading out of
+/// the blame repo's `history/timeline` tree, an HTTP backend, a test double,
+/// etc).  Kept as a trait rather than a closure so implementors can hold
+/// onto connection/cache state across calls.
+#[async_trait::async_trait]
+pub trait TimelineRecordLoader: Sync {
+    async fn load_summary(&self, timeline_rev: &str) -> Result<SummaryRecordRef, TimelineStreamError>;
+    async fn load_detail(&self, timeline_rev: &str, source_rev: &str) -> Result<DetailRecordRef, TimelineStreamError>;
+}
+
+/// Walks backward from `start_timeline_rev`, yielding `Summary` records and
+/// then, for each `source_revs` entry they name, the corresponding `Detail`
+/// record, before following `pred_timeline_rev` to the next summary.
+///
+/// If `oldest_iso_week_cutoff` is set, the walk stops (without error) as soon
+/// as it reaches a summary record whose `iso_week_range` is entirely older
+/// than the cutoff, so a consumer can stream e.g. "all detail records from
+/// the last 8 weeks" without paging through the entire history.
+///
+/// A fetch error is yielded as an `Err` item and ends the stream -- we can't
+/// know `pred_timeline_rev` for a summary we failed to load, so there's
+/// nothing further to walk to; the caller sees the error rather than the
+/// stream just silently going quiet.
+pub fn stream_timeline_chain<'a>(
+    loader: &'a dyn TimelineRecordLoader,
+    start_timeline_rev: String,
+    oldest_iso_week_cutoff: Option<(u16, u8, u8)>,
+) -> impl Stream<Item = Result<TimelineChainItem, TimelineStreamError>> + 'a {
+    stream! {
+        let mut cur_rev = Some(start_timeline_rev);
+
+        while let Some(timeline_rev) = cur_rev.take() {
+            let summary = match loader.load_summary(&timeline_rev).await {
+                Ok(summary) => summary,
+                Err(err) => {
+                    yield Err(err);
+                    return;
+                }
+            };
+
+            if let Some(cutoff) = oldest_iso_week_cutoff {
+                if summary.iso_week_range < cutoff {
+                    return;
+                }
+            }
+
+            let pred = summary.pred_timeline_rev.clone();
+            let source_revs = summary.source_revs.clone();
+            yield Ok(TimelineChainItem::Summary(summary));
+
+            for source_rev in &source_revs {
+                match loader.load_detail(&timeline_rev, source_rev).await {
+                    Ok(detail) => yield Ok(TimelineChainItem::Detail(detail)),
+                    Err(err) => {
+                        yield Err(err);
+                        return;
+                    }
+                }
+            }
+
+            if !pred.is_empty() {
+                cur_rev = Some(pred);
+            }
+        }
+    }
+}
@@ -22,8 +22,12 @@
 //! to efficiently perform filtering by intersecting commit sets before moving
 //! on to look up the commits.
 
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
+use super::timeline_common::{TokenDeltaDetails, SCHEMA_VERSION};
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TokenDeltaDetailRecord {
     #[serde(flatten)]
@@ -51,3 +55,52 @@ pub enum TokenDeltaRecord {
     Detail(TokenDeltaDetailRecord),
     Summary(TokenDeltaSummaryRecord),
 }
+
+/// Canonical author identity ("Name <email>") as resolved through `.mailmap`
+/// by `canonical_author_identity` in `build-timeline-tree`, used as the map
+/// key below so the same contributor publishing under several addresses
+/// collapses into one entry instead of fragmenting the rollup, the same way
+/// `git shortlog -e` groups commits.
+pub type AuthorIdentity = String;
+
+/// Token-churn-weighted `git shortlog`: per-author totals, accumulated
+/// revision by revision as `TokenStatsMachine`'s output is folded in by
+/// `write_token_author_rollups`.  One of these is written per file under
+/// `token-authors/` and, merged across every file beneath it, per directory
+/// under `token-authors-by-dir/`, so the web UI can answer "who wrote most
+/// of the tokens here" without re-deriving it from the full revision
+/// history at query time.
+///
+/// Like the `tokens/` directory this module's doc comment above describes,
+/// these files are never recomputed from scratch; each revision reads the
+/// prior record from its blame parent and folds its own delta in, so the
+/// accumulation is cheap regardless of how long the history is.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuthorTokenRollupRecord {
+    /// Schema version this record was written at; see `DetailRecordRef::version`.
+    pub version: u32,
+    pub by_author: BTreeMap<AuthorIdentity, TokenDeltaDetails>,
+}
+
+impl AuthorTokenRollupRecord {
+    pub fn empty() -> Self {
+        AuthorTokenRollupRecord {
+            version: SCHEMA_VERSION,
+            by_author: BTreeMap::new(),
+        }
+    }
+
+    pub fn accumulate(&mut self, author: AuthorIdentity, delta: &TokenDeltaDetails) {
+        let entry = self.by_author.entry(author).or_insert(TokenDeltaDetails {
+            added: 0,
+            moved: 0,
+            evolved_from: 0,
+            removed: 0,
+        });
+        entry.added += delta.added;
+        entry.moved += delta.moved;
+        entry.evolved_from += delta.evolved_from;
+        entry.removed += delta.removed;
+    }
+}